@@ -0,0 +1,208 @@
+use super::swap::SwapFile;
+use crate::editor::component::textarea::Textarea;
+
+// A buffer open in the editor, with the file it's bound to and the state that goes with it
+// The textarea keeps the cursor and scroll position, so they're kept while other buffers are shown
+pub struct BufferEntry {
+    // Buffers keep the number they were given when opened, like in Vim
+    pub number: usize,
+    pub textarea: Textarea,
+    pub path: Option<String>,
+    pub read_only: bool,
+    // Whether the file exists but its content couldn't be read, so that writing the buffer
+    // would replace the content with nothing, which even `:w!` must not do
+    pub load_failed: bool,
+    pub swap_file: Option<SwapFile>,
+    // The buffer version that was last written to the swap file
+    pub swap_version: usize,
+    // Whether an existing swap file has been looked for, which is done when the buffer is first
+    // shown
+    pub swap_checked: bool,
+}
+
+impl BufferEntry {
+    pub fn new(textarea: Textarea, path: Option<String>) -> BufferEntry {
+        BufferEntry {
+            number: 0,
+            textarea,
+            path,
+            read_only: false,
+            load_failed: false,
+            swap_file: None,
+            swap_version: 0,
+            swap_checked: false,
+        }
+    }
+
+    pub fn get_name(&self) -> &str {
+        self.path.as_deref().unwrap_or("[No Name]")
+    }
+
+    // An empty buffer that was never named or edited, which opening a file can take the place of
+    pub fn is_pristine(&self) -> bool {
+        let buffer = self.textarea.get_content();
+        self.path.is_none() && buffer.get_version() == 0 && !buffer.is_modified()
+    }
+}
+
+// The open buffers, in the order they were opened, one of which is shown
+// There's always at least one buffer, an empty one if nothing else is open
+pub struct BufferList {
+    entries: Vec<BufferEntry>,
+    current: usize,
+    next_number: usize,
+}
+
+impl Default for BufferList {
+    fn default() -> BufferList {
+        let mut buffer_list = BufferList {
+            entries: vec![],
+            current: 0,
+            next_number: 1,
+        };
+        buffer_list.add(BufferEntry::new(Textarea::new(""), None));
+        buffer_list
+    }
+}
+
+impl BufferList {
+    // Add a buffer after the others, returning its index
+    pub fn add(&mut self, mut entry: BufferEntry) -> usize {
+        entry.number = self.next_number;
+        self.next_number += 1;
+        self.entries.push(entry);
+        self.entries.len() - 1
+    }
+
+    // Put a buffer in the place of another, keeping the number of the one it replaces
+    pub fn replace(&mut self, idx: usize, mut entry: BufferEntry) {
+        entry.number = self.entries[idx].number;
+        self.entries[idx] = entry;
+    }
+
+    // Remove a buffer, showing the one after it (or before it if it was the last) if it was shown
+    pub fn remove(&mut self, idx: usize) -> BufferEntry {
+        if self.entries.len() == 1 {
+            self.add(BufferEntry::new(Textarea::new(""), None));
+        }
+        let entry = self.entries.remove(idx);
+        if self.current > idx || self.current == self.entries.len() {
+            self.current -= 1;
+        }
+        entry
+    }
+
+    pub fn get_current_idx(&self) -> usize {
+        self.current
+    }
+
+    pub fn get_current(&self) -> &BufferEntry {
+        &self.entries[self.current]
+    }
+
+    pub fn get_current_mut(&mut self) -> &mut BufferEntry {
+        &mut self.entries[self.current]
+    }
+
+    pub fn select(&mut self, idx: usize) {
+        self.current = idx.min(self.entries.len() - 1);
+    }
+
+    // The index of the buffer `count` places after the current one, wrapping around
+    pub fn get_relative_idx(&self, count: isize) -> usize {
+        let len = self.entries.len() as isize;
+        (self.current as isize + count).rem_euclid(len) as usize
+    }
+
+    pub fn get_count(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &BufferEntry> {
+        self.entries.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut BufferEntry> {
+        self.entries.iter_mut()
+    }
+
+    pub fn find_by_number(&self, number: usize) -> Option<usize> {
+        self.entries.iter().position(|entry| entry.number == number)
+    }
+
+    pub fn get_by_number(&self, number: usize) -> Option<&BufferEntry> {
+        self.entries.iter().find(|entry| entry.number == number)
+    }
+
+    pub fn get_by_number_mut(&mut self, number: usize) -> Option<&mut BufferEntry> {
+        self.entries.iter_mut().find(|entry| entry.number == number)
+    }
+
+    pub fn find_by_path(&self, path: &str) -> Option<usize> {
+        self.entries
+            .iter()
+            .position(|entry| entry.path.as_deref() == Some(path))
+    }
+
+    // Find a buffer by its number, its name, or a part of its name that only one buffer has
+    pub fn find(&self, name: &str) -> Result<usize, String> {
+        if let Ok(number) = name.parse::<usize>() {
+            return self
+                .find_by_number(number)
+                .ok_or(format!("Buffer {number} does not exist"));
+        }
+        if let Some(idx) = self.find_by_path(name) {
+            return Ok(idx);
+        }
+        let mut matches = self
+            .entries
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| entry.path.as_ref().is_some_and(|path| path.contains(name)));
+        match (matches.next(), matches.next()) {
+            (Some((idx, _)), None) => Ok(idx),
+            (Some(_), Some(_)) => Err(format!("More than one match for {name}")),
+            (None, _) => Err(format!("No matching buffer for {name}")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_buffer_list(paths: &[&str]) -> BufferList {
+        let mut buffer_list = BufferList::default();
+        buffer_list.remove(0);
+        for path in paths {
+            buffer_list.add(BufferEntry::new(Textarea::new(""), Some(path.to_string())));
+        }
+        buffer_list.remove(0);
+        buffer_list
+    }
+
+    #[test]
+    fn find_by_number_path_or_unique_part() {
+        let buffer_list = get_buffer_list(&["src/main.rs", "src/lib.rs", "README.md"]);
+        assert_eq!(buffer_list.find("4"), Ok(1));
+        assert!(buffer_list.find("9").is_err());
+        assert_eq!(buffer_list.find("README.md"), Ok(2));
+        assert_eq!(buffer_list.find("main"), Ok(0));
+        assert!(buffer_list.find("src").is_err());
+        assert!(buffer_list.find("missing").is_err());
+    }
+
+    #[test]
+    fn remove_keeps_a_buffer_shown() {
+        let mut buffer_list = get_buffer_list(&["a", "b", "c"]);
+        buffer_list.select(2);
+        buffer_list.remove(2);
+        assert_eq!(buffer_list.get_current().get_name(), "b");
+        buffer_list.select(0);
+        buffer_list.remove(1);
+        assert_eq!(buffer_list.get_current().get_name(), "a");
+        buffer_list.remove(0);
+        assert_eq!(buffer_list.get_count(), 1);
+        assert!(buffer_list.get_current().is_pristine());
+    }
+}
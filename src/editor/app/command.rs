@@ -0,0 +1,113 @@
+// A command entered in the command area, without the leading `:`
+pub enum Command {
+    Set(Vec<String>),
+    Colorscheme(Option<String>),
+    Write { force: bool },
+    Quit { force: bool },
+    WriteQuit { force: bool },
+    Messages,
+    Edit(String),
+    BufferNext,
+    BufferPrevious,
+    Buffer(String),
+    Buffers,
+    BufferDelete { name: Option<String>, force: bool },
+    Split(Option<String>),
+    VerticalSplit(Option<String>),
+    Close,
+    Only,
+    TabNew(Option<String>),
+    TabClose,
+    TabNext,
+    TabPrevious,
+}
+
+impl Command {
+    pub fn parse(line: &str) -> Result<Command, String> {
+        let line = line.trim();
+        let (name, args) = line.split_once(' ').unwrap_or((line, ""));
+        let args = Self::split_args(args);
+        match name {
+            "set" | "se" => Ok(Command::Set(args)),
+            "colorscheme" | "colo" => Ok(Command::Colorscheme(args.first().cloned())),
+            "write" | "w" => Ok(Command::Write { force: false }),
+            "write!" | "w!" => Ok(Command::Write { force: true }),
+            "quit" | "q" => Ok(Command::Quit { force: false }),
+            "quit!" | "q!" => Ok(Command::Quit { force: true }),
+            "wq" | "x" => Ok(Command::WriteQuit { force: false }),
+            "wq!" | "x!" => Ok(Command::WriteQuit { force: true }),
+            "messages" | "mes" => Ok(Command::Messages),
+            "edit" | "e" => match args.first() {
+                Some(path) => Ok(Command::Edit(path.clone())),
+                None => Err("Argument required".into()),
+            },
+            "bnext" | "bn" => Ok(Command::BufferNext),
+            "bprevious" | "bprev" | "bp" => Ok(Command::BufferPrevious),
+            "buffer" | "b" => match args.first() {
+                Some(name) => Ok(Command::Buffer(name.clone())),
+                None => Err("Argument required".into()),
+            },
+            "buffers" | "ls" | "files" => Ok(Command::Buffers),
+            "bdelete" | "bd" => Ok(Command::BufferDelete {
+                name: args.first().cloned(),
+                force: false,
+            }),
+            "split" | "sp" => Ok(Command::Split(args.first().cloned())),
+            "vsplit" | "vs" => Ok(Command::VerticalSplit(args.first().cloned())),
+            "close" | "clo" => Ok(Command::Close),
+            "only" | "on" => Ok(Command::Only),
+            "tabnew" | "tabedit" | "tabe" => Ok(Command::TabNew(args.first().cloned())),
+            "tabclose" | "tabc" => Ok(Command::TabClose),
+            "tabnext" | "tabn" => Ok(Command::TabNext),
+            "tabprevious" | "tabp" | "tabNext" | "tabN" => Ok(Command::TabPrevious),
+            "bdelete!" | "bd!" => Ok(Command::BufferDelete {
+                name: args.first().cloned(),
+                force: true,
+            }),
+            _ => Err(format!("Not an editor command: {line}")),
+        }
+    }
+
+    // Split the arguments at whitespace, except for spaces escaped with a backslash
+    fn split_args(args: &str) -> Vec<String> {
+        let mut split_args = vec![];
+        let mut arg = String::new();
+        let mut chars = args.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some(' ') => arg.push(' '),
+                    Some(next) => {
+                        arg.push('\\');
+                        arg.push(next);
+                    }
+                    None => arg.push('\\'),
+                },
+                c if c.is_whitespace() => {
+                    if !arg.is_empty() {
+                        split_args.push(std::mem::take(&mut arg));
+                    }
+                }
+                c => arg.push(c),
+            }
+        }
+        if !arg.is_empty() {
+            split_args.push(arg);
+        }
+        split_args
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_args_keeps_escaped_spaces() {
+        assert_eq!(
+            Command::split_args("  a\\ b  c\\d e\\"),
+            vec!["a b", "c\\d", "e\\"]
+        );
+        assert!(Command::split_args(" \t ").is_empty());
+    }
+}
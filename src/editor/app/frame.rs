@@ -0,0 +1,22 @@
+use super::window::WindowId;
+use crate::editor::drawing_surface::DrawingSurface;
+
+// A window's part of the screen, its text above its status line
+pub struct WindowFrame {
+    pub id: WindowId,
+    pub text: Box<dyn DrawingSurface>,
+    pub status_line: Box<dyn DrawingSurface>,
+}
+
+// The parts of the screen, laid out once per frame and used both to draw and to place the
+// cursor, so that the two always agree
+pub struct Frame {
+    // The whole screen, for popups
+    pub screen: Box<dyn DrawingSurface>,
+    pub tab_line: Box<dyn DrawingSurface>,
+    pub windows: Vec<WindowFrame>,
+    // The columns between windows side by side
+    pub separators: Vec<Box<dyn DrawingSurface>>,
+    // The command-line row, or the pager while it's open
+    pub command_line: Box<dyn DrawingSurface>,
+}
@@ -0,0 +1,226 @@
+use crate::editor::{
+    drawing_surface::DrawingSurface, theme::HighlightGroup, utility::TerminalPosition,
+    width::DisplayWidth,
+};
+use crossterm::event::{KeyCode, KeyEvent};
+use std::collections::VecDeque;
+use unicode_segmentation::UnicodeSegmentation;
+
+// Only the most recent messages are kept for `:messages`
+const HISTORY_SIZE: usize = 200;
+
+const MORE_PROMPT: &str = "-- More -- (SPACE: next page, j: next line, q: quit)";
+const CONTINUE_PROMPT: &str = "Press ENTER or type command to continue";
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum MessageLevel {
+    Info,
+    Warning,
+    Error,
+}
+
+impl MessageLevel {
+    fn get_group(&self) -> HighlightGroup {
+        match self {
+            MessageLevel::Info => HighlightGroup::Normal,
+            MessageLevel::Warning => HighlightGroup::WarningMsg,
+            MessageLevel::Error => HighlightGroup::ErrorMsg,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Message {
+    level: MessageLevel,
+    text: String,
+}
+
+impl Message {
+    pub fn new(level: MessageLevel, text: &str) -> Message {
+        Message {
+            level,
+            text: text.into(),
+        }
+    }
+
+    // The rows the message takes when shown `width` columns wide, keeping its own line breaks
+    pub fn wrap(&self, width: usize) -> Vec<String> {
+        let mut rows = vec![];
+        for line in self.text.lines() {
+            let mut row = String::new();
+            let mut row_width = 0;
+            for grapheme in line.graphemes(true) {
+                let grapheme_width = grapheme.display_width();
+                if row_width + grapheme_width > width && !row.is_empty() {
+                    rows.push(std::mem::take(&mut row));
+                    row_width = 0;
+                }
+                row.push_str(grapheme);
+                row_width += grapheme_width;
+            }
+            rows.push(row);
+        }
+        rows
+    }
+
+    // Whether the message fits in one row of this width
+    pub fn fits(&self, width: usize) -> bool {
+        !self.text.contains('\n') && self.text.display_width() <= width
+    }
+
+    pub fn draw(&self, surface: &mut dyn DrawingSurface) {
+        Self::draw_row(surface, &self.text, self.level.get_group(), 0);
+    }
+
+    fn draw_row(surface: &mut dyn DrawingSurface, text: &str, group: HighlightGroup, row: u16) {
+        if group != HighlightGroup::Normal {
+            surface.add_highlight(
+                group,
+                None,
+                TerminalPosition { col: 0, row },
+                TerminalPosition {
+                    col: text.display_width() as u16,
+                    row,
+                },
+            );
+        }
+        surface.add_content(text, TerminalPosition { col: 0, row });
+    }
+}
+
+// The messages reported so far and the one currently shown in the command-line row
+#[derive(Default)]
+pub struct MessageLog {
+    history: VecDeque<Message>,
+    current: Option<Message>,
+}
+
+impl MessageLog {
+    pub fn push(&mut self, level: MessageLevel, text: &str) {
+        let message = Message::new(level, text);
+        if self.history.len() == HISTORY_SIZE {
+            self.history.pop_front();
+        }
+        self.history.push_back(message.clone());
+        self.current = Some(message);
+    }
+
+    pub fn get_current(&self) -> Option<&Message> {
+        self.current.as_ref()
+    }
+
+    pub fn take_current(&mut self) -> Option<Message> {
+        self.current.take()
+    }
+
+    pub fn clear_current(&mut self) {
+        self.current = None;
+    }
+
+    pub fn get_history(&self) -> Vec<Message> {
+        self.history.iter().cloned().collect()
+    }
+}
+
+// Shows messages that don't fit in the command-line row over the bottom of the screen,
+// a page at a time when they don't fit on the screen either
+pub struct Pager {
+    messages: Vec<Message>,
+    // The rows of the messages as last laid out, with the group each is drawn with
+    rows: Vec<(String, HighlightGroup)>,
+    // The first row shown and how many rows fit above the prompt
+    top: usize,
+    page_height: usize,
+}
+
+impl Pager {
+    pub fn new(messages: Vec<Message>) -> Pager {
+        Pager {
+            messages,
+            rows: vec![],
+            top: 0,
+            page_height: 1,
+        }
+    }
+
+    // The number of screen rows the pager takes, including its prompt, on a screen of this size
+    pub fn layout(&mut self, width: u16, height: u16) -> u16 {
+        self.rows = self
+            .messages
+            .iter()
+            .flat_map(|message| {
+                let group = message.level.get_group();
+                message
+                    .wrap(width as usize)
+                    .into_iter()
+                    .map(move |row| (row, group))
+            })
+            .collect();
+        self.page_height = (height as usize).saturating_sub(1).max(1);
+        self.top = self
+            .top
+            .min(self.rows.len().saturating_sub(self.page_height));
+        (self.rows.len().min(self.page_height) + 1).min(height as usize) as u16
+    }
+
+    fn is_at_end(&self) -> bool {
+        self.top + self.page_height >= self.rows.len()
+    }
+
+    fn get_prompt(&self) -> &'static str {
+        if self.is_at_end() {
+            CONTINUE_PROMPT
+        } else {
+            MORE_PROMPT
+        }
+    }
+
+    // Draw into a surface of the height `layout` returned
+    pub fn draw(&self, surface: &mut dyn DrawingSurface) {
+        let height = surface.get_bounding_rect_size().height;
+        let shown = self.rows.iter().skip(self.top).take(self.page_height);
+        for (row, (text, group)) in shown.enumerate() {
+            Message::draw_row(surface, text, *group, row as u16);
+        }
+        Message::draw_row(
+            surface,
+            self.get_prompt(),
+            HighlightGroup::MoreMsg,
+            height.saturating_sub(1),
+        );
+    }
+
+    pub fn focus(&self, surface: &mut dyn DrawingSurface) {
+        let height = surface.get_bounding_rect_size().height;
+        surface.add_cursor(TerminalPosition {
+            col: self.get_prompt().display_width() as u16,
+            row: height.saturating_sub(1),
+        });
+    }
+
+    // Returns whether the pager is done and should be closed
+    pub fn handle_key(&mut self, event: KeyEvent) -> bool {
+        if !event.is_press() {
+            return false;
+        }
+        if self.is_at_end() {
+            return true;
+        }
+        let last_top = self.rows.len().saturating_sub(self.page_height);
+        match event.code {
+            KeyCode::Char(' ') | KeyCode::PageDown => {
+                self.top = (self.top + self.page_height).min(last_top);
+            }
+            KeyCode::Char('j') | KeyCode::Enter | KeyCode::Down => {
+                self.top = (self.top + 1).min(last_top);
+            }
+            KeyCode::Char('k') | KeyCode::Up => self.top = self.top.saturating_sub(1),
+            KeyCode::Char('b') | KeyCode::PageUp => {
+                self.top = self.top.saturating_sub(self.page_height);
+            }
+            KeyCode::Char('q') | KeyCode::Esc => return true,
+            _ => {}
+        }
+        false
+    }
+}
@@ -1,101 +1,633 @@
 use super::{
-    component::textarea::Textarea,
-    drawing_surface::DrawingSurface,
-    utility::{Style, TerminalPosition},
+    component::{
+        statusline::{StatusInfo, detect_file_type},
+        tabline::{self, TabLabel},
+        textarea::{Textarea, buffer::Buffer},
+    },
+    drawing_surface::{
+        DrawingSurface,
+        layout::{Constraint, Layout},
+    },
+    options::Options,
+    theme::{Background, HighlightGroup, Theme},
+    utility::{CursorShape, Direction, TerminalPosition, TerminalSize},
 };
-use crossterm::event::{KeyCode, KeyEvent};
+use buffer_list::{BufferEntry, BufferList};
+use command::Command;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+pub use frame::Frame;
+use frame::WindowFrame;
+use message::{Message, MessageLevel, MessageLog, Pager};
+use prompt::Prompt;
 use std::{
-    fs::File,
-    io::{Error, Read},
-    path::PathBuf,
+    fs::{self, File, OpenOptions},
+    io::{BufWriter, Error, ErrorKind, Read, Write},
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::Duration,
 };
+use swap::SwapFile;
+use tab::TabPages;
+use window::{SplitDirection, WindowId, WindowLayout};
+
+mod buffer_list;
+mod command;
+mod frame;
+mod message;
+mod prompt;
+mod swap;
+mod tab;
+mod window;
 
 #[derive(Clone, Copy, PartialEq, Eq)]
 enum EditorMode {
     Insert,
     Normal,
+    Replace,
     Command,
 }
 
+impl EditorMode {
+    fn get_name(&self) -> &'static str {
+        match self {
+            EditorMode::Insert => "INSERT",
+            EditorMode::Normal => "NORMAL",
+            EditorMode::Replace => "REPLACE",
+            EditorMode::Command => "COMMAND",
+        }
+    }
+}
+
+// The possible answers when a swap file is found for the file being opened
+#[derive(Clone, Copy)]
+enum SwapAnswer {
+    Recover,
+    OpenReadOnly,
+    Delete,
+    Abort,
+}
+
+// The possible answers when the directory of the file being written doesn't exist
+#[derive(Clone, Copy)]
+enum CreateDirAnswer {
+    Create { quit: bool, force: bool },
+    Cancel,
+}
+
 pub struct App {
-    content_area: Textarea,
+    buffers: BufferList,
+    // The tab pages, each with windows showing the buffers, the current buffer being the one
+    // in the active window of the current tab page
+    tabs: TabPages,
+    // The size of the screen the last frame was laid out on
+    screen_size: TerminalSize,
     command_area: Textarea,
-    path: Option<String>,
     mode: EditorMode,
+    // The keys typed so far of a Normal mode command that takes more than one key
+    pending_keys: String,
+    options: Options,
+    theme: Rc<Theme>,
+    // The background kind derived from the terminal's default background color
+    detected_background: Background,
+    swap_prompt: Option<Prompt<SwapAnswer>>,
+    create_dir_prompt: Option<Prompt<CreateDirAnswer>>,
+    messages: MessageLog,
+    // Shows the messages that don't fit in the command-line row, until the user dismisses it
+    pager: Option<Pager>,
+    quit: bool,
 }
 
 impl App {
-    pub fn new(path: Option<PathBuf>) -> Result<App, Error> {
-        if path.is_none() {
-            Ok(App {
-                content_area: Textarea::new(""),
-                command_area: Textarea::new(""),
-                path: None,
-                mode: EditorMode::Normal,
-            })
-        } else {
-            let file_path = path.unwrap().to_string_lossy().into_owned();
-            let mut file = File::open(&file_path)?;
+    pub fn new(paths: Vec<PathBuf>) -> App {
+        let mut app = App {
+            buffers: BufferList::default(),
+            tabs: TabPages::new(WindowLayout::new(0)),
+            screen_size: TerminalSize::default(),
+            command_area: Textarea::new(""),
+            mode: EditorMode::Normal,
+            pending_keys: String::new(),
+            options: Options::default(),
+            theme: Rc::new(Theme::default()),
+            detected_background: Background::default(),
+            swap_prompt: None,
+            create_dir_prompt: None,
+            messages: MessageLog::default(),
+            pager: None,
+            quit: false,
+        };
+        for path in paths {
+            app.open_buffer(&path.to_string_lossy());
+        }
+        app.select_buffer(0);
+        app.tabs = TabPages::new(WindowLayout::new(app.buffers.get_current().number));
+        app
+    }
+
+    // Open a file in a new buffer, returning its index, or the index of the buffer it's
+    // already open in
+    // A missing file gets an empty buffer, the file is created when the buffer is first written
+    // The new buffer takes the place of the current one if that's empty and unnamed, unless
+    // other windows are showing it
+    fn open_buffer(&mut self, path: &str) -> usize {
+        if let Some(idx) = self.buffers.find_by_path(path) {
+            return idx;
+        }
+        let read = || -> Result<String, Error> {
             let mut content = String::new();
-            file.read_to_string(&mut content)?;
-            Ok(App {
-                content_area: Textarea::new(&content),
-                command_area: Textarea::new(""),
-                path: Some(file_path),
-                mode: EditorMode::Normal,
+            File::open(path)?.read_to_string(&mut content)?;
+            Ok(content)
+        };
+        let mut entry = BufferEntry::new(Textarea::new(""), Some(path.into()));
+        match read() {
+            Ok(content) => {
+                entry.textarea = Textarea::new(&content);
+                let buffer = entry.textarea.get_content();
+                let mut message = format!(
+                    "\"{path}\" {}L, {}B",
+                    buffer.get_line_count(),
+                    buffer.get_byte_count()
+                );
+                let mut level = MessageLevel::Info;
+                if !Self::is_writable(path) {
+                    entry.read_only = true;
+                    level = MessageLevel::Warning;
+                    message = format!("\"{path}\" [RO] No permission to write, opened read-only");
+                }
+                self.messages.push(level, &message);
+                entry.swap_file = Some(SwapFile::for_file(path));
+            }
+            Err(error) if error.kind() == ErrorKind::NotFound => {
+                self.messages
+                    .push(MessageLevel::Info, &format!("\"{path}\" [New]"));
+                entry.swap_file = Some(SwapFile::for_file(path));
+            }
+            Err(error) if error.kind() == ErrorKind::PermissionDenied => {
+                entry.read_only = true;
+                entry.load_failed = true;
+                self.messages.push(
+                    MessageLevel::Warning,
+                    &format!("\"{path}\" [RO] No permission to read, opened read-only"),
+                );
+            }
+            Err(error) => {
+                entry.read_only = true;
+                entry.load_failed = true;
+                self.messages
+                    .push(MessageLevel::Error, &format!("Can't open {path}: {error}"));
+            }
+        }
+        entry.textarea.apply_options(&self.options);
+
+        let current = self.buffers.get_current_idx();
+        let current_entry = self.buffers.get_current();
+        if current_entry.is_pristine() && self.count_windows_showing(current_entry.number) <= 1 {
+            self.buffers.replace(current, entry);
+            current
+        } else {
+            self.buffers.add(entry)
+        }
+    }
+
+    // The number of windows in every tab page showing the buffer `number`
+    fn count_windows_showing(&self, number: usize) -> usize {
+        self.tabs
+            .iter()
+            .flat_map(WindowLayout::iter)
+            .filter(|window| window.buffer_number == number)
+            .count()
+    }
+
+    // Show a buffer, asking what to do about its swap file the first time it's shown
+    fn select_buffer(&mut self, idx: usize) {
+        self.buffers.select(idx);
+        if !self.buffers.get_current().swap_checked {
+            self.buffers.get_current_mut().swap_checked = true;
+            self.check_swap_file();
+        }
+    }
+
+    // Show a buffer in the active window
+    fn show_buffer(&mut self, idx: usize) {
+        self.select_buffer(idx);
+        let window = self.tabs.get_current_mut().get_active_mut();
+        window.buffer_number = self.buffers.get_current().number;
+        window.view = None;
+    }
+
+    // Remove a buffer from the list, the windows of every tab page that showed it show the
+    // current buffer instead
+    fn remove_buffer(&mut self, idx: usize) -> BufferEntry {
+        let entry = self.buffers.remove(idx);
+        let current = self.buffers.get_current().number;
+        for window in self.tabs.iter_mut().flat_map(WindowLayout::iter_mut) {
+            if window.buffer_number == entry.number {
+                window.buffer_number = current;
+                window.view = None;
+            }
+        }
+        self.select_buffer(self.buffers.get_current_idx());
+        entry
+    }
+
+    // Delete a buffer, refusing to lose its changes unless forced to
+    fn delete_buffer(&mut self, idx: usize, force: bool) -> Result<(), String> {
+        let Some(entry) = self.buffers.iter().nth(idx) else {
+            return Ok(());
+        };
+        if !force && entry.textarea.get_content().is_modified() {
+            return Err(format!(
+                "No write since last change for buffer {} (add ! to override)",
+                entry.number
+            ));
+        }
+        let entry = self.remove_buffer(idx);
+        // A swap file that hasn't been looked at yet may belong to another editor
+        if entry.swap_checked
+            && let Some(swap_file) = &entry.swap_file
+        {
+            let _ = swap_file.delete();
+        }
+        Ok(())
+    }
+
+    // The `:ls` listing: number, flags (`%` shown, `=` read-only, `+` modified), name and line
+    fn get_buffer_listing(&self) -> String {
+        let current = self.buffers.get_current_idx();
+        let lines: Vec<String> = self
+            .buffers
+            .iter()
+            .enumerate()
+            .map(|(idx, entry)| {
+                let flag = |set: bool, flag: char| if set { flag } else { ' ' };
+                let buffer = entry.textarea.get_content();
+                format!(
+                    "{:>3} {}{}{} \"{}\" line {}",
+                    entry.number,
+                    flag(idx == current, '%'),
+                    flag(entry.read_only, '='),
+                    flag(buffer.is_modified(), '+'),
+                    entry.get_name(),
+                    buffer.get_grapheme_location().line + 1,
+                )
+            })
+            .collect();
+        lines.join("\n")
+    }
+
+    // Whether the existing file at the path can be opened for writing, without changing it
+    fn is_writable(path: &str) -> bool {
+        !matches!(
+            OpenOptions::new().append(true).open(path),
+            Err(error) if error.kind() == ErrorKind::PermissionDenied
+        )
+    }
+
+    pub fn get_options(&self) -> &Options {
+        &self.options
+    }
+
+    pub fn get_theme(&self) -> Rc<Theme> {
+        self.theme.clone()
+    }
+
+    // Called once the terminal has reported its default colors, if it does
+    // Light text is taken as a sign of a dark background when only the foreground is known
+    pub fn set_terminal_colors(
+        &mut self,
+        foreground: Option<(u8, u8, u8)>,
+        background: Option<(u8, u8, u8)>,
+    ) {
+        let from_foreground = |foreground| match Background::from_rgb(foreground) {
+            Background::Dark => Background::Light,
+            Background::Light => Background::Dark,
+        };
+        self.detected_background = background
+            .map(Background::from_rgb)
+            .or(foreground.map(from_foreground))
+            .unwrap_or_default();
+        let _ = self.reload_theme_for_background();
+    }
+
+    fn get_background(&self) -> Background {
+        self.options.background.unwrap_or(self.detected_background)
+    }
+
+    fn reload_theme_for_background(&mut self) -> Result<(), String> {
+        let background = self.get_background();
+        if self.theme.get_background() != background {
+            self.theme = Rc::new(Theme::load(self.theme.get_name(), background)?);
+        }
+        Ok(())
+    }
+
+    pub fn should_quit(&self) -> bool {
+        self.quit
+    }
+
+    // Swap files are only written once the user has been idle for this long
+    pub fn get_idle_timeout(&self) -> Duration {
+        Duration::from_millis(self.options.update_time)
+    }
+
+    pub fn handle_idle(&mut self) {
+        self.write_swap_files();
+    }
+
+    // Called when the editor exits normally, the user has chosen to keep or discard their edits
+    // Quit from wherever the editor is, as Ctrl-Q does, unless a buffer has unwritten changes
+    pub fn quit(&mut self) {
+        match self.check_modified_buffers(None) {
+            Ok(()) => self.quit = true,
+            Err(message) => self.messages.push(MessageLevel::Error, &message),
+        }
+    }
+
+    pub fn close(&mut self) {
+        let current = self.buffers.get_current_idx();
+        for (idx, entry) in self.buffers.iter().enumerate() {
+            // A swap file that hasn't been looked at yet may belong to another editor
+            if !entry.swap_checked || (idx == current && self.swap_prompt.is_some()) {
+                continue;
+            }
+            if let Some(swap_file) = &entry.swap_file {
+                let _ = swap_file.delete();
+            }
+        }
+    }
+
+    // The tab line, shown when there are several tab pages, the windows, and the command line
+    fn get_screen_layout(&self, command_line_height: u16) -> Layout<3> {
+        let tab_line_height = if self.tabs.get_count() > 1 { 1 } else { 0 };
+        Layout::rows([
+            Constraint::Fixed(tab_line_height),
+            Constraint::Min(1),
+            Constraint::Fixed(command_line_height),
+        ])
+    }
+
+    // The size of the windows' part of the screen, worked out from the current tab pages rather
+    // than taken from the last frame, which is out of date once the tab line shows or hides
+    // Window commands can't be given while the pager is open, so the command line is one row
+    fn get_window_area_size(&self) -> TerminalSize {
+        let [_, height, _] = self.get_screen_layout(1).get_sizes(self.screen_size.height);
+        TerminalSize {
+            width: self.screen_size.width,
+            height,
+        }
+    }
+
+    // Lay out the screen for a frame: the tab line, the windows, and the command-line row, or
+    // the pager, which covers as much of the screen as its messages need
+    pub fn layout(&mut self, screen: Box<dyn DrawingSurface>) -> Frame {
+        let size = screen.get_bounding_rect_size();
+        if self.pager.is_none()
+            && self
+                .messages
+                .get_current()
+                .is_some_and(|message| !message.fits(size.width as usize))
+            && let Some(message) = self.messages.take_current()
+        {
+            self.pager = Some(Pager::new(vec![message]));
+        }
+        let command_line_height = match &mut self.pager {
+            Some(pager) => pager.layout(size.width, size.height),
+            None => 1,
+        };
+        self.screen_size = size;
+        let [tab_line, window_area, command_line] = self
+            .get_screen_layout(command_line_height)
+            .split(screen.as_ref());
+        let surfaces = self.tabs.get_current().slice(window_area);
+        let window_layout = Layout::rows([Constraint::Fill, Constraint::Fixed(1)]);
+        let windows = surfaces
+            .windows
+            .into_iter()
+            .map(|(id, surface)| {
+                let [text, status_line] = window_layout.split(surface.as_ref());
+                WindowFrame {
+                    id,
+                    text,
+                    status_line,
+                }
             })
+            .collect();
+
+        Frame {
+            screen,
+            tab_line,
+            windows,
+            separators: surfaces.separators,
+            command_line,
         }
     }
 
-    pub fn draw(&mut self, surface: &mut dyn DrawingSurface) {
-        let (top_surface, mut bottom_surface) = surface.slice_bottom_horizontal(1);
-        let (mut top_surface, mut mid_surface) = top_surface.slice_bottom_horizontal(1);
+    pub fn draw(&mut self, frame: &mut Frame) {
+        self.draw_tab_line(frame.tab_line.as_mut());
+        self.draw_windows(&mut frame.windows, &mut frame.separators);
+        if let Some(pager) = &mut self.pager {
+            pager.draw(frame.command_line.as_mut());
+            return;
+        }
+        match self.messages.get_current() {
+            Some(message) if self.mode != EditorMode::Command => {
+                message.draw(frame.command_line.as_mut())
+            }
+            _ => self.command_area.draw(frame.command_line.as_mut()),
+        }
+        if let Some(message) = self.get_prompt_message() {
+            prompt::draw(frame.screen.as_mut(), message);
+        }
+    }
+
+    // The question that's waiting for an answer, if any
+    fn get_prompt_message(&self) -> Option<&str> {
+        self.swap_prompt
+            .as_ref()
+            .map(Prompt::get_message)
+            .or(self.create_dir_prompt.as_ref().map(Prompt::get_message))
+    }
 
-        self.content_area.draw(top_surface.as_mut());
-        self.draw_powerline(mid_surface.as_mut());
-        self.command_area.draw(bottom_surface.as_mut());
+    fn draw_tab_line(&self, surface: &mut dyn DrawingSurface) {
+        let labels: Vec<TabLabel> = self
+            .tabs
+            .iter()
+            .map(|layout| {
+                let entry = self
+                    .buffers
+                    .get_by_number(layout.get_active().buffer_number);
+                TabLabel {
+                    name: entry.map_or("", BufferEntry::get_name).into(),
+                    modified: entry.is_some_and(|entry| entry.textarea.get_content().is_modified()),
+                    window_count: layout.get_count(),
+                }
+            })
+            .collect();
+        tabline::draw(surface, &labels, self.tabs.get_current_idx());
+    }
+
+    // Draw every window with its own view of its buffer
+    // The textarea of a buffer shown in several windows takes each window's view in turn,
+    // and is left with the active window's view for editing
+    fn draw_windows(
+        &mut self,
+        windows: &mut [WindowFrame],
+        separators: &mut [Box<dyn DrawingSurface>],
+    ) {
+        self.save_active_view();
+        for window in windows {
+            self.draw_window(window);
+        }
+        for separator in separators {
+            for row in 0..separator.get_bounding_rect_size().height {
+                separator.add_highlight(
+                    HighlightGroup::WinSeparator,
+                    None,
+                    TerminalPosition { col: 0, row },
+                    TerminalPosition { col: 1, row },
+                );
+                separator.add_content("│", TerminalPosition { col: 0, row });
+            }
+        }
+        self.load_active_view();
+    }
+
+    fn draw_window(&mut self, frame: &mut WindowFrame) {
+        let window = self.tabs.get_current_mut().get_mut(frame.id);
+        let buffer_number = window.buffer_number;
+        let Some(entry) = self.buffers.get_by_number_mut(buffer_number) else {
+            return;
+        };
+        if let Some(view) = window.view {
+            entry.textarea.set_view(view);
+        }
+        entry.textarea.draw(frame.text.as_mut());
+        window.view = Some(entry.textarea.get_view());
+
+        let active = frame.id == self.tabs.get_current().get_active_id();
+        self.draw_status_line(frame.status_line.as_mut(), buffer_number, active);
+    }
+
+    fn save_active_view(&mut self) {
+        let view = self.buffers.get_current().textarea.get_view();
+        self.tabs.get_current_mut().get_active_mut().view = Some(view);
+    }
+
+    // Make the active window's buffer the current one, with the window's view
+    fn load_active_view(&mut self) {
+        let window = self.tabs.get_current().get_active();
+        let view = window.view;
+        if let Some(idx) = self.buffers.find_by_number(window.buffer_number) {
+            self.select_buffer(idx);
+        }
+        if let Some(view) = view {
+            self.buffers.get_current_mut().textarea.set_view(view);
+        }
     }
 
-    fn draw_powerline(&mut self, surface: &mut dyn DrawingSurface) {
-        let pathname = if self.path.is_none() {
-            "[No name]"
+    fn draw_status_line(
+        &self,
+        surface: &mut dyn DrawingSurface,
+        buffer_number: usize,
+        active: bool,
+    ) {
+        let Some(entry) = self.buffers.get_by_number(buffer_number) else {
+            return;
+        };
+        let buffer = entry.textarea.get_content();
+        let location = buffer.get_grapheme_location();
+        let info = StatusInfo {
+            mode: if active { self.mode.get_name() } else { "" },
+            buffer_number: entry.number,
+            file_name: entry.path.as_deref(),
+            modified: buffer.is_modified(),
+            read_only: entry.read_only,
+            file_type: entry.path.as_deref().and_then(detect_file_type),
+            line_ending: buffer.get_line_ending(),
+            line: location.line + 1,
+            offset: location.offset + 1,
+            col: entry.textarea.get_cursor_col() + 1,
+            line_count: buffer.get_line_count(),
+            pending_keys: if active { &self.pending_keys } else { "" },
+        };
+        let group = if active {
+            HighlightGroup::StatusLine
         } else {
-            self.path.as_ref().unwrap()
+            HighlightGroup::StatusLineNC
         };
-        let line_count = self.content_area.get_content().get_line_count();
-        surface.add_content(
-            &format!("{pathname} - {line_count} lines"),
-            TerminalPosition { col: 0, row: 0 },
-        );
-        surface.add_styles(
-            vec![Style::Inverted(true)],
-            TerminalPosition { col: 0, row: 0 },
-            TerminalPosition {
-                col: surface.get_bounding_rect_size().width,
-                row: 0,
-            },
-        );
+        self.options.status_line.draw(surface, &info, group);
     }
 
-    pub fn focus(&mut self, surface: &mut dyn DrawingSurface) {
-        let (top_surface, mut bottom_surface) = surface.slice_bottom_horizontal(1);
-        let (mut top_surface, _) = top_surface.slice_bottom_horizontal(1);
+    pub fn focus(&mut self, frame: &mut Frame) {
+        frame.screen.set_cursor_shape(self.get_cursor_shape());
+        if let Some(pager) = &mut self.pager {
+            pager.focus(frame.command_line.as_mut());
+            return;
+        }
+        if let Some(message) = self.get_prompt_message() {
+            prompt::focus(frame.screen.as_mut(), message);
+            return;
+        }
+        match self.mode {
+            EditorMode::Command => self.command_area.focus(frame.command_line.as_mut()),
+            _ => {
+                let active = self.tabs.get_current().get_active_id();
+                if let Some(window) = frame.windows.iter_mut().find(|window| window.id == active) {
+                    self.buffers
+                        .get_current_mut()
+                        .textarea
+                        .focus(window.text.as_mut());
+                }
+            }
+        }
+    }
 
+    fn get_cursor_shape(&self) -> CursorShape {
+        let shapes = &self.options.cursor_shapes;
         match self.mode {
-            EditorMode::Command => self.command_area.focus(bottom_surface.as_mut()),
-            _ => self.content_area.focus(top_surface.as_mut()),
+            EditorMode::Normal => shapes.normal,
+            EditorMode::Insert => shapes.insert,
+            EditorMode::Replace => shapes.replace,
+            EditorMode::Command => shapes.command,
         }
     }
 
     pub fn handle_key(&mut self, event: KeyEvent) {
-        if self.handle_key_top_level(event) {
+        if let Some(pager) = &mut self.pager {
+            if pager.handle_key(event) {
+                self.pager = None;
+                // Like at Vim's hit-enter prompt, `:` goes straight on to the next command
+                if event.code == KeyCode::Char(':') {
+                    self.handle_key_top_level(event);
+                }
+            }
+            return;
+        }
+        if self.swap_prompt.is_some() {
+            self.handle_key_swap_prompt(event);
             return;
         }
+        if self.create_dir_prompt.is_some() {
+            self.handle_key_create_dir_prompt(event);
+            return;
+        }
+        if !self.handle_key_top_level(event) {
+            match self.mode {
+                EditorMode::Insert => self.buffers.get_current_mut().textarea.handle_key(event),
+                EditorMode::Replace => self
+                    .buffers
+                    .get_current_mut()
+                    .textarea
+                    .handle_key_replace(event),
+                EditorMode::Command => self.command_area.handle_key(event),
+                _ => {}
+            }
+        }
 
-        match self.mode {
-            EditorMode::Insert => self.content_area.handle_key(event),
-            EditorMode::Command => self.command_area.handle_key(event),
-            _ => {}
+        let update_count = self.options.update_count;
+        let entry = self.buffers.get_current();
+        let version = entry.textarea.get_content().get_version();
+        if update_count > 0 && version >= entry.swap_version + update_count {
+            self.write_swap_files();
         }
     }
 
@@ -105,38 +637,681 @@ impl App {
         }
         if event.code == KeyCode::Esc {
             self.mode = EditorMode::Normal;
+            self.pending_keys.clear();
             self.command_area.set_content("");
+            self.messages.clear_current();
             return true;
         }
         match self.mode {
-            EditorMode::Insert => {
-                return false;
-            }
+            EditorMode::Insert | EditorMode::Replace => false,
             EditorMode::Command => {
                 if event.code == KeyCode::Enter {
+                    let line = self
+                        .command_area
+                        .get_content()
+                        .get_line(0)
+                        .unwrap_or_default();
                     self.command_area.set_content("");
                     self.mode = EditorMode::Normal;
+                    if let Err(message) = self.execute_command(line.trim_start_matches(':')) {
+                        self.messages.push(MessageLevel::Error, &message);
+                    }
                     return true;
                 }
-                return false;
+                false
             }
             EditorMode::Normal => {
+                if !self.pending_keys.is_empty() {
+                    self.handle_key_pending(event);
+                    return true;
+                }
                 match event.code {
+                    KeyCode::Char('i' | 'R') if self.buffers.get_current().read_only => {
+                        self.messages
+                            .push(MessageLevel::Warning, "The file is read-only");
+                    }
                     KeyCode::Char('i') => {
                         self.mode = EditorMode::Insert;
                     }
+                    KeyCode::Char('R') => {
+                        self.mode = EditorMode::Replace;
+                    }
                     KeyCode::Char(':') => {
                         self.mode = EditorMode::Command;
+                        self.messages.clear_current();
                         self.command_area.set_content(":");
                         self.command_area.move_to_end_of_current_line();
                     }
+                    KeyCode::Char('w') if event.modifiers.contains(KeyModifiers::CONTROL) => {
+                        self.pending_keys.push_str("^W");
+                    }
+                    KeyCode::Char(c @ ('g' | 'm' | '\'')) => self.pending_keys.push(c),
                     KeyCode::Up | KeyCode::Left | KeyCode::Right | KeyCode::Down => {
-                        self.content_area.handle_key(event);
+                        self.buffers.get_current_mut().textarea.handle_key(event);
                     }
                     _ => {}
                 };
-                return true;
+                true
+            }
+        }
+    }
+
+    // Complete a Normal mode command that started with the pending keys
+    // An unknown combination is dropped as a whole, like Vim does
+    fn handle_key_pending(&mut self, event: KeyEvent) {
+        let pending_keys = std::mem::take(&mut self.pending_keys);
+        match (pending_keys.as_str(), event.code) {
+            ("g", KeyCode::Char('j')) => self
+                .buffers
+                .get_current_mut()
+                .textarea
+                .move_screen_row(Direction::Down),
+            ("g", KeyCode::Char('k')) => self
+                .buffers
+                .get_current_mut()
+                .textarea
+                .move_screen_row(Direction::Up),
+            ("g", KeyCode::Char('t')) => self.focus_tab(self.tabs.get_relative_idx(1)),
+            ("g", KeyCode::Char('T')) => self.focus_tab(self.tabs.get_relative_idx(-1)),
+            ("m", KeyCode::Char(name)) if name.is_ascii_lowercase() => {
+                self.buffers.get_current_mut().textarea.set_mark(name);
+            }
+            ("'", KeyCode::Char(name)) if name.is_ascii_lowercase() => {
+                if let Err(message) = self.buffers.get_current_mut().textarea.jump_to_mark(name) {
+                    self.messages.push(MessageLevel::Error, &message);
+                }
+            }
+            ("^W", code) => {
+                if let Err(message) = self.handle_key_window(code) {
+                    self.messages.push(MessageLevel::Error, &message);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // The window commands that follow Ctrl-W
+    fn handle_key_window(&mut self, code: KeyCode) -> Result<(), String> {
+        let size = self.get_window_area_size();
+        match code {
+            KeyCode::Char('h') | KeyCode::Left => self.focus_neighbor(Direction::Left),
+            KeyCode::Char('j') | KeyCode::Down => self.focus_neighbor(Direction::Down),
+            KeyCode::Char('k') | KeyCode::Up => self.focus_neighbor(Direction::Up),
+            KeyCode::Char('l') | KeyCode::Right => self.focus_neighbor(Direction::Right),
+            KeyCode::Char('w') => self.focus_window(self.tabs.get_current().get_relative_window(1)),
+            KeyCode::Char('W') => {
+                self.focus_window(self.tabs.get_current().get_relative_window(-1))
+            }
+            KeyCode::Char('s' | 'S') => self.split_window(SplitDirection::Horizontal, None)?,
+            KeyCode::Char('v') => self.split_window(SplitDirection::Vertical, None)?,
+            KeyCode::Char('c') => self.close_window()?,
+            KeyCode::Char('q') => self.quit_window(false)?,
+            KeyCode::Char('o') => self.tabs.get_current_mut().only(),
+            KeyCode::Char('+') => {
+                self.tabs
+                    .get_current_mut()
+                    .resize(SplitDirection::Horizontal, 1, size)
+            }
+            KeyCode::Char('-') => {
+                self.tabs
+                    .get_current_mut()
+                    .resize(SplitDirection::Horizontal, -1, size)
+            }
+            KeyCode::Char('>') => {
+                self.tabs
+                    .get_current_mut()
+                    .resize(SplitDirection::Vertical, 1, size)
+            }
+            KeyCode::Char('<') => {
+                self.tabs
+                    .get_current_mut()
+                    .resize(SplitDirection::Vertical, -1, size)
+            }
+            KeyCode::Char('=') => self.tabs.get_current_mut().equalize(),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn focus_window(&mut self, id: WindowId) {
+        self.save_active_view();
+        self.tabs.get_current_mut().set_active(id);
+        self.load_active_view();
+    }
+
+    fn focus_neighbor(&mut self, direction: Direction) {
+        if let Some(id) = self
+            .tabs
+            .get_current()
+            .find_neighbor(direction, self.get_window_area_size())
+        {
+            self.focus_window(id);
+        }
+    }
+
+    // Split the active window, showing a file in the new window if one is given
+    fn split_window(
+        &mut self,
+        direction: SplitDirection,
+        path: Option<&str>,
+    ) -> Result<(), String> {
+        self.save_active_view();
+        let size = self.get_window_area_size();
+        self.tabs.get_current_mut().split(direction, size)?;
+        if let Some(path) = path {
+            let idx = self.open_buffer(path);
+            self.show_buffer(idx);
+        }
+        Ok(())
+    }
+
+    fn close_window(&mut self) -> Result<(), String> {
+        self.save_active_view();
+        let active = self.tabs.get_current().get_active_id();
+        self.tabs.get_current_mut().close(active)?;
+        self.load_active_view();
+        Ok(())
+    }
+
+    // Open a tab page with a single window, showing a file if one is given or an empty buffer
+    fn new_tab(&mut self, path: Option<&str>) {
+        self.save_active_view();
+        // The new tab page starts out on the current buffer, so opening the file can't take
+        // the place of a buffer still shown in the other tab page
+        self.tabs
+            .add(WindowLayout::new(self.buffers.get_current().number));
+        let idx = match path {
+            Some(path) => self.open_buffer(path),
+            None => {
+                let mut entry = BufferEntry::new(Textarea::new(""), None);
+                entry.textarea.apply_options(&self.options);
+                self.buffers.add(entry)
+            }
+        };
+        self.show_buffer(idx);
+    }
+
+    fn focus_tab(&mut self, idx: usize) {
+        self.save_active_view();
+        self.tabs.select(idx);
+        self.load_active_view();
+    }
+
+    // Close the current tab page, its buffers stay open
+    fn close_tab(&mut self) -> Result<(), String> {
+        self.tabs.close_current()?;
+        self.load_active_view();
+        Ok(())
+    }
+
+    // Close the active window, closing its tab page if it's the last window there, or quit if
+    // it's the last window of all
+    fn quit_window(&mut self, force: bool) -> Result<(), String> {
+        if self.tabs.get_current().get_count() > 1 {
+            return self.close_window();
+        }
+        if self.tabs.get_count() > 1 {
+            return self.close_tab();
+        }
+        if !force {
+            self.check_modified_buffers(None)?;
+        }
+        self.quit = true;
+        Ok(())
+    }
+
+    fn execute_command(&mut self, line: &str) -> Result<(), String> {
+        match Command::parse(line)? {
+            Command::Set(assignments) => {
+                for assignment in assignments {
+                    self.options.set(&assignment)?;
+                }
+                for entry in self.buffers.iter_mut() {
+                    entry.textarea.apply_options(&self.options);
+                }
+                self.reload_theme_for_background()?;
+            }
+            Command::Colorscheme(None) => {
+                let name = self.theme.get_name().to_string();
+                self.messages.push(MessageLevel::Info, &name);
+            }
+            Command::Colorscheme(Some(name)) => {
+                self.theme = Rc::new(Theme::load(&name, self.get_background())?)
+            }
+            Command::Write { force } => self.write_file(false, force)?,
+            Command::Quit { force } => self.quit_window(force)?,
+            Command::WriteQuit { force } => self.write_file(true, force)?,
+            Command::Messages => {
+                let history = self.messages.get_history();
+                if !history.is_empty() {
+                    self.messages.clear_current();
+                    self.pager = Some(Pager::new(history));
+                }
+            }
+            Command::Edit(path) => {
+                let idx = self.open_buffer(&path);
+                self.show_buffer(idx);
+            }
+            Command::BufferNext => self.show_buffer(self.buffers.get_relative_idx(1)),
+            Command::BufferPrevious => self.show_buffer(self.buffers.get_relative_idx(-1)),
+            Command::Buffer(name) => {
+                let idx = self.buffers.find(&name)?;
+                self.show_buffer(idx);
+            }
+            Command::Buffers => {
+                let listing = Message::new(MessageLevel::Info, &self.get_buffer_listing());
+                self.messages.clear_current();
+                self.pager = Some(Pager::new(vec![listing]));
+            }
+            Command::BufferDelete { name, force } => {
+                let idx = match name {
+                    Some(name) => self.buffers.find(&name)?,
+                    None => self.buffers.get_current_idx(),
+                };
+                self.delete_buffer(idx, force)?;
+            }
+            Command::Split(path) => {
+                self.split_window(SplitDirection::Horizontal, path.as_deref())?
+            }
+            Command::VerticalSplit(path) => {
+                self.split_window(SplitDirection::Vertical, path.as_deref())?
+            }
+            Command::Close => self.close_window()?,
+            Command::Only => self.tabs.get_current_mut().only(),
+            Command::TabNew(path) => self.new_tab(path.as_deref()),
+            Command::TabClose => self.close_tab()?,
+            Command::TabNext => self.focus_tab(self.tabs.get_relative_idx(1)),
+            Command::TabPrevious => self.focus_tab(self.tabs.get_relative_idx(-1)),
+        }
+        Ok(())
+    }
+
+    // Fail if a buffer other than `except` has changes that quitting would lose
+    fn check_modified_buffers(&self, except: Option<usize>) -> Result<(), String> {
+        match self.buffers.iter().find(|entry| {
+            Some(entry.number) != except && entry.textarea.get_content().is_modified()
+        }) {
+            Some(entry) => Err(format!(
+                "No write since last change for buffer {} ({}) (add ! to override)",
+                entry.number,
+                entry.get_name()
+            )),
+            None => Ok(()),
+        }
+    }
+
+    // Write the buffer to its file and quit afterwards if asked to
+    // If the file's directory doesn't exist, the user is asked whether to create it first
+    fn write_file(&mut self, quit: bool, force: bool) -> Result<(), String> {
+        let entry = self.buffers.get_current();
+        if entry.load_failed {
+            return Err("Can't write a file that couldn't be read".into());
+        }
+        if entry.read_only && !force {
+            return Err("File is read-only (add ! to override)".into());
+        }
+        // Refuse a :wq that can't quit before writing, rather than after
+        if quit && self.tabs.get_count() == 1 && self.tabs.get_current().get_count() == 1 {
+            self.check_modified_buffers(Some(entry.number))?;
+        }
+        let path = entry.path.clone().ok_or("No file name")?;
+        if let Some(dir) = Path::new(&path).parent()
+            && !dir.as_os_str().is_empty()
+            && !dir.exists()
+        {
+            let message = format!(
+                "Directory {} doesn't exist, create it? [Y]es, [N]o",
+                dir.display()
+            );
+            self.create_dir_prompt = Some(Prompt::new(
+                &message,
+                vec![
+                    ('y', CreateDirAnswer::Create { quit, force }),
+                    ('n', CreateDirAnswer::Cancel),
+                    ('q', CreateDirAnswer::Cancel),
+                ],
+            ));
+            return Ok(());
+        }
+
+        let is_new = !Path::new(&path).exists();
+        let write = || -> Result<(), Error> {
+            let mut writer = BufWriter::new(File::create(&path)?);
+            entry.textarea.get_content().write_to(&mut writer)?;
+            writer.flush()
+        };
+        write().map_err(|error| format!("Can't write {path}: {error}"))?;
+        let buffer = entry.textarea.get_content();
+        let message = format!(
+            "\"{path}\" {}{}L, {}B written",
+            if is_new { "[New] " } else { "" },
+            buffer.get_line_count(),
+            buffer.get_byte_count()
+        );
+        self.messages.push(MessageLevel::Info, &message);
+
+        let entry = self.buffers.get_current_mut();
+        entry.textarea.get_content_mut().set_modified(false);
+        entry.read_only = false;
+        if let Some(swap_file) = &entry.swap_file {
+            let _ = swap_file.delete();
+        }
+        entry.swap_version = entry.textarea.get_content().get_version();
+        if quit {
+            self.quit_window(false)?;
+        }
+        Ok(())
+    }
+
+    fn handle_key_create_dir_prompt(&mut self, event: KeyEvent) {
+        let Some(prompt) = &self.create_dir_prompt else {
+            return;
+        };
+        let answer = match event.code {
+            KeyCode::Char(c) if event.is_press() => prompt.answer(c),
+            KeyCode::Esc if event.is_press() => Some(CreateDirAnswer::Cancel),
+            _ => None,
+        };
+        let Some(answer) = answer else {
+            return;
+        };
+        self.create_dir_prompt = None;
+
+        let CreateDirAnswer::Create { quit, force } = answer else {
+            return;
+        };
+        let Some(dir) = self
+            .buffers
+            .get_current()
+            .path
+            .as_ref()
+            .and_then(|path| Path::new(path).parent())
+            .map(Path::to_path_buf)
+        else {
+            return;
+        };
+        let result = fs::create_dir_all(&dir)
+            .map_err(|error| format!("Can't create directory {}: {error}", dir.display()))
+            .and_then(|()| self.write_file(quit, force));
+        if let Err(message) = result {
+            self.messages.push(MessageLevel::Error, &message);
+        }
+    }
+
+    fn check_swap_file(&mut self) {
+        let Some(swap_file) = &self.buffers.get_current().swap_file else {
+            return;
+        };
+        if !swap_file.exists() {
+            return;
+        }
+        let details = match swap_file.read() {
+            Ok(swap) if swap.modified => format!("process {}, unsaved changes", swap.pid),
+            Ok(swap) => format!("process {}, no unsaved changes", swap.pid),
+            Err(_) => "unreadable".into(),
+        };
+        let message = format!(
+            "Found swap file {} ({details}): [R]ecover, [O]pen read-only, [D]elete it, [A]bort",
+            swap_file.get_path().display(),
+        );
+        self.swap_prompt = Some(Prompt::new(
+            &message,
+            vec![
+                ('r', SwapAnswer::Recover),
+                ('o', SwapAnswer::OpenReadOnly),
+                ('d', SwapAnswer::Delete),
+                ('a', SwapAnswer::Abort),
+                ('q', SwapAnswer::Abort),
+            ],
+        ));
+    }
+
+    fn handle_key_swap_prompt(&mut self, event: KeyEvent) {
+        let Some(prompt) = &self.swap_prompt else {
+            return;
+        };
+        let answer = match event.code {
+            KeyCode::Char(c) if event.is_press() => prompt.answer(c),
+            KeyCode::Esc if event.is_press() => Some(SwapAnswer::Abort),
+            _ => None,
+        };
+        let Some(answer) = answer else {
+            return;
+        };
+        self.swap_prompt = None;
+        let entry = self.buffers.get_current_mut();
+        let Some(swap_file) = &entry.swap_file else {
+            return;
+        };
+
+        // Unless the user chooses to take over the swap file, it's left as is for later recovery
+        let mut keep_swap_file = true;
+        match answer {
+            SwapAnswer::Recover => match swap_file.read() {
+                Ok(swap) => {
+                    let mut textarea = Textarea::new(&swap.content);
+                    textarea.get_content_mut().move_grapheme_to(swap.cursor);
+                    textarea.get_content_mut().set_modified(swap.modified);
+                    textarea.apply_options(&self.options);
+                    entry.textarea = textarea;
+                }
+                Err(error) => {
+                    self.messages.push(
+                        MessageLevel::Error,
+                        &format!("Can't recover swap file: {error}"),
+                    );
+                    entry.read_only = true;
+                    keep_swap_file = false;
+                }
+            },
+            SwapAnswer::OpenReadOnly => {
+                entry.read_only = true;
+                keep_swap_file = false;
+            }
+            SwapAnswer::Delete => {
+                let _ = swap_file.delete();
+            }
+            // Aborting only closes the buffer if other buffers are open
+            SwapAnswer::Abort => {
+                entry.swap_file = None;
+                if self.buffers.get_count() == 1 {
+                    self.quit = true;
+                } else {
+                    self.remove_buffer(self.buffers.get_current_idx());
+                }
+                return;
+            }
+        }
+        if !keep_swap_file {
+            entry.swap_file = None;
+        }
+    }
+
+    fn write_swap_files(&mut self) {
+        if self.swap_prompt.is_some() {
+            return;
+        }
+        for entry in self.buffers.iter_mut() {
+            let Some(swap_file) = &entry.swap_file else {
+                continue;
+            };
+            let buffer = entry.textarea.get_content();
+            if buffer.get_version() == entry.swap_version {
+                continue;
+            }
+            if swap_file.write(buffer).is_ok() {
+                entry.swap_version = buffer.get_version();
             }
         }
     }
+
+    // Write an emergency copy of every modified buffer, returning where each copy ended up
+    pub fn write_recovery_files(&self) -> Vec<Result<PathBuf, Error>> {
+        self.buffers
+            .iter()
+            .filter(|entry| entry.textarea.get_content().is_modified())
+            .map(|entry| {
+                Self::write_recovery_file(entry.path.as_deref(), entry.textarea.get_content())
+            })
+            .collect()
+    }
+
+    // The copy is put next to the original file if possible, otherwise in the temp directory
+    fn write_recovery_file(path: Option<&str>, buffer: &Buffer) -> Result<PathBuf, Error> {
+        let file_name = path
+            .and_then(|path| Path::new(path).file_name())
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or(format!("t-rex-{}", std::process::id()));
+        let fallback_path = std::env::temp_dir().join(format!("{file_name}.recovered"));
+        let candidates = match path {
+            Some(path) => vec![PathBuf::from(format!("{path}.recovered")), fallback_path],
+            None => vec![fallback_path],
+        };
+
+        let mut last_error = Error::other("No recovery location available");
+        for recovery_path in candidates {
+            let write = || -> Result<(), Error> {
+                let mut writer = BufWriter::new(File::create(&recovery_path)?);
+                buffer.write_to(&mut writer)?;
+                writer.flush()
+            };
+            match write() {
+                Ok(()) => return Ok(recovery_path),
+                Err(error) => last_error = error,
+            }
+        }
+        Err(last_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_refuses_after_failed_read() {
+        let dir = std::env::temp_dir().join(format!("t-rex-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("not-utf-8.txt");
+        fs::write(&path, [0xff, 0xfe, 0x00]).unwrap();
+
+        let mut app = App::new(vec![path.clone()]);
+        assert!(app.buffers.get_current().read_only);
+        assert!(app.execute_command("w").is_err());
+        assert_eq!(fs::read(&path).unwrap(), [0xff, 0xfe, 0x00]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn forced_write_keeps_a_file_that_failed_to_load() {
+        let dir = std::env::temp_dir().join(format!("t-rex-test-force-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("not-utf-8.txt");
+        fs::write(&path, [0xff, 0xfe, 0x00]).unwrap();
+
+        let mut app = App::new(vec![path.clone()]);
+        assert!(app.execute_command("w!").is_err());
+        assert!(app.execute_command("wq!").is_err());
+        assert_eq!(fs::read(&path).unwrap(), [0xff, 0xfe, 0x00]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn forced_write_overrides_read_only() {
+        let dir = std::env::temp_dir().join(format!("t-rex-test-ro-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("file.txt");
+        fs::write(&path, "text\n").unwrap();
+
+        let mut app = App::new(vec![path.clone()]);
+        app.buffers.get_current_mut().read_only = true;
+        assert!(app.execute_command("w").is_err());
+        assert!(app.execute_command("w!").is_ok());
+        assert!(!app.buffers.get_current().read_only);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "text\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn write_quit_checks_other_buffers_before_writing() {
+        let dir = std::env::temp_dir().join(format!("t-rex-test-wq-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (first, second) = (dir.join("first.txt"), dir.join("second.txt"));
+        fs::write(&first, "first\n").unwrap();
+        fs::write(&second, "second\n").unwrap();
+
+        let mut app = App::new(vec![first.clone(), second]);
+        for entry in app.buffers.iter_mut() {
+            entry.textarea.get_content_mut().set_modified(true);
+        }
+        app.buffers.get_current_mut().textarea = Textarea::new("changed\n");
+        app.buffers
+            .get_current_mut()
+            .textarea
+            .get_content_mut()
+            .set_modified(true);
+        assert!(app.execute_command("wq").is_err());
+        assert!(!app.should_quit());
+        assert_eq!(fs::read_to_string(&first).unwrap(), "first\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn opening_a_file_in_a_new_window_keeps_the_empty_buffer() {
+        let dir = std::env::temp_dir().join(format!("t-rex-test-split-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("file.txt");
+        fs::write(&path, "text\n").unwrap();
+        let path = path.to_string_lossy();
+
+        let mut app = App::new(vec![]);
+        app.screen_size = TerminalSize {
+            width: 80,
+            height: 24,
+        };
+        app.execute_command(&format!("split {path}")).unwrap();
+        app.execute_command(&format!("tabnew {path}")).unwrap();
+        assert_eq!(app.buffers.get_count(), 2);
+        let shown: Vec<usize> = app
+            .tabs
+            .iter()
+            .flat_map(WindowLayout::iter)
+            .map(|window| window.buffer_number)
+            .collect();
+        assert_eq!(shown.iter().filter(|number| **number == 1).count(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn window_area_follows_the_tab_line() {
+        let mut app = App::new(vec![]);
+        app.screen_size = TerminalSize {
+            width: 80,
+            height: 24,
+        };
+        assert_eq!(app.get_window_area_size().height, 23);
+        app.execute_command("tabnew").unwrap();
+        assert_eq!(app.get_window_area_size().height, 22);
+        app.execute_command("tabclose").unwrap();
+        assert_eq!(app.get_window_area_size().height, 23);
+    }
+
+    #[test]
+    fn ctrl_q_keeps_modified_buffers() {
+        let mut app = App::new(vec![]);
+        app.buffers
+            .get_current_mut()
+            .textarea
+            .get_content_mut()
+            .type_char('x');
+        app.quit();
+        assert!(!app.should_quit());
+        app.buffers
+            .get_current_mut()
+            .textarea
+            .get_content_mut()
+            .set_modified(false);
+        app.quit();
+        assert!(app.should_quit());
+    }
 }
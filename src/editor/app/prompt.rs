@@ -0,0 +1,98 @@
+use super::message::{Message, MessageLevel};
+use crate::editor::{
+    drawing_surface::DrawingSurface,
+    utility::{TerminalPosition, TerminalSize},
+    width::DisplayWidth,
+};
+
+// The message is wrapped to at most this many columns
+const MAX_WIDTH: u16 = 60;
+
+// A question shown in a popup that is answered by pressing one of the choice keys
+pub struct Prompt<T> {
+    message: String,
+    choices: Vec<(char, T)>,
+}
+
+impl<T: Copy> Prompt<T> {
+    pub fn new(message: &str, choices: Vec<(char, T)>) -> Prompt<T> {
+        Prompt {
+            message: message.into(),
+            choices,
+        }
+    }
+
+    pub fn get_message(&self) -> &str {
+        &self.message
+    }
+
+    pub fn answer(&self, key: char) -> Option<T> {
+        self.choices
+            .iter()
+            .find(|(choice_key, _)| choice_key.eq_ignore_ascii_case(&key))
+            .map(|(_, answer)| *answer)
+    }
+}
+
+// Where the popup of a prompt goes in the middle of a surface of the given size, the size of its
+// content and the rows of the message
+fn layout(message: &str, size: TerminalSize) -> (TerminalPosition, TerminalSize, Vec<String>) {
+    let width = (message.display_width() as u16 + 1)
+        .min(MAX_WIDTH)
+        .min(size.width.saturating_sub(2))
+        .max(1);
+    // The last column of every row is left for the cursor, which goes after the last row
+    let rows =
+        Message::new(MessageLevel::Info, message).wrap(width.saturating_sub(1).max(1) as usize);
+    let content_size = TerminalSize {
+        width,
+        height: rows.len() as u16,
+    };
+    let origin = TerminalPosition {
+        col: size.width.saturating_sub(width + 2) / 2,
+        row: size.height.saturating_sub(content_size.height + 2) / 2,
+    };
+    (origin, content_size, rows)
+}
+
+pub fn draw(surface: &mut dyn DrawingSurface, message: &str) {
+    let (origin, size, rows) = layout(message, surface.get_bounding_rect_size());
+    let mut popup = surface.add_popup(origin, size);
+    for (row, text) in rows.iter().enumerate() {
+        popup.add_content(
+            text,
+            TerminalPosition {
+                col: 0,
+                row: row as u16,
+            },
+        );
+    }
+}
+
+// Put the cursor after the message, inside the popup's border
+pub fn focus(surface: &mut dyn DrawingSurface, message: &str) {
+    let (origin, _, rows) = layout(message, surface.get_bounding_rect_size());
+    let last_row = rows.last().map_or(0, |row| row.display_width());
+    surface.add_cursor(TerminalPosition {
+        col: origin.col + 1 + last_row as u16,
+        row: origin.row + rows.len() as u16,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layout_leaves_the_cursor_column_on_every_row() {
+        let size = TerminalSize {
+            width: 12,
+            height: 10,
+        };
+        let (_, content_size, rows) = layout("aaaaaaaaaaaaaaaaaaaa", size);
+        assert_eq!(content_size.width, 10);
+        assert_eq!(rows, vec!["aaaaaaaaa", "aaaaaaaaa", "aa"]);
+        let (_, _, rows) = layout("aaaaaaaaaaaaaaaaaa", size);
+        assert!(rows.iter().all(|row| row.display_width() < 10));
+    }
+}
@@ -0,0 +1,130 @@
+use crate::editor::{component::textarea::buffer::Buffer, utility::GraphemeLocation};
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Error, ErrorKind, Read, Write},
+    path::{Path, PathBuf},
+};
+
+const SWAP_HEADER: &str = "t-rex swap 1";
+
+// The content of a swap file, as written by an editor that has possibly crashed since
+pub struct SwapContent {
+    pub pid: u32,
+    pub cursor: GraphemeLocation,
+    pub modified: bool,
+    pub content: String,
+}
+
+// A swap file keeps a copy of a buffer being edited, so that the edits can be recovered
+// if the editor dies before they are written, e.g. when an SSH session drops
+// It lives next to the edited file: `dir/file.rs` has the swap file `dir/.file.rs.swp`
+pub struct SwapFile {
+    path: PathBuf,
+}
+
+impl SwapFile {
+    pub fn for_file(file_path: &str) -> SwapFile {
+        let file_path = Path::new(file_path);
+        let file_name = file_path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        SwapFile {
+            path: file_path.with_file_name(format!(".{file_name}.swp")),
+        }
+    }
+
+    pub fn get_path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn exists(&self) -> bool {
+        self.path.exists()
+    }
+
+    // The swap file format is a header of `key value` lines, an empty line, then the content
+    pub fn write(&self, buffer: &Buffer) -> Result<(), Error> {
+        let GraphemeLocation { line, offset } = buffer.get_grapheme_location();
+        let mut writer = BufWriter::new(File::create(&self.path)?);
+        writeln!(writer, "{SWAP_HEADER}")?;
+        writeln!(writer, "pid {}", std::process::id())?;
+        writeln!(writer, "cursor {line} {offset}")?;
+        writeln!(writer, "modified {}", buffer.is_modified() as u8)?;
+        writeln!(writer)?;
+        buffer.write_to(&mut writer)?;
+        writer.flush()
+    }
+
+    pub fn read(&self) -> Result<SwapContent, Error> {
+        let mut raw = String::new();
+        File::open(&self.path)?.read_to_string(&mut raw)?;
+
+        let invalid = || Error::new(ErrorKind::InvalidData, "Invalid swap file");
+        let (header, content) = raw.split_once("\n\n").ok_or_else(invalid)?;
+        let mut header_lines = header.lines();
+        if header_lines.next() != Some(SWAP_HEADER) {
+            return Err(invalid());
+        }
+
+        let mut swap = SwapContent {
+            pid: 0,
+            cursor: GraphemeLocation::default(),
+            modified: true,
+            content: content.into(),
+        };
+        for header_line in header_lines {
+            let (key, value) = header_line.split_once(' ').ok_or_else(invalid)?;
+            let numbers = value
+                .split(' ')
+                .map(|number| number.parse::<usize>().map_err(|_| invalid()))
+                .collect::<Result<Vec<_>, _>>()?;
+            match (key, numbers.as_slice()) {
+                ("pid", [pid]) => swap.pid = *pid as u32,
+                ("cursor", [line, offset]) => {
+                    swap.cursor = GraphemeLocation {
+                        line: *line,
+                        offset: *offset,
+                    }
+                }
+                ("modified", [modified]) => swap.modified = *modified != 0,
+                _ => {}
+            }
+        }
+        Ok(swap)
+    }
+
+    pub fn delete(&self) -> Result<(), Error> {
+        match fs::remove_file(&self.path) {
+            Err(error) if error.kind() != ErrorKind::NotFound => Err(error),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_returns_what_write_wrote() {
+        let dir = std::env::temp_dir().join(format!("t-rex-test-swap-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let swap_file = SwapFile::for_file(dir.join("file.txt").to_str().unwrap());
+        assert_eq!(swap_file.get_path(), dir.join(".file.txt.swp"));
+
+        let mut buffer = Buffer::new("one\n\ntwo\n");
+        buffer.move_grapheme_to(GraphemeLocation { line: 2, offset: 1 });
+        buffer.type_char('x');
+        swap_file.write(&buffer).unwrap();
+        let swap = swap_file.read().unwrap();
+        assert_eq!(swap.pid, std::process::id());
+        assert_eq!((swap.cursor.line, swap.cursor.offset), (2, 2));
+        assert!(swap.modified);
+        assert_eq!(swap.content, "one\n\ntxwo\n");
+
+        swap_file.delete().unwrap();
+        assert!(!swap_file.exists());
+        assert!(swap_file.delete().is_ok());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
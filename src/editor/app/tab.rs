@@ -0,0 +1,66 @@
+use super::window::WindowLayout;
+
+// The tab pages, each with its own arrangement of windows, of which one is shown
+pub struct TabPages {
+    tabs: Vec<WindowLayout>,
+    current: usize,
+}
+
+impl TabPages {
+    pub fn new(layout: WindowLayout) -> TabPages {
+        TabPages {
+            tabs: vec![layout],
+            current: 0,
+        }
+    }
+
+    pub fn get_current(&self) -> &WindowLayout {
+        &self.tabs[self.current]
+    }
+
+    pub fn get_current_mut(&mut self) -> &mut WindowLayout {
+        &mut self.tabs[self.current]
+    }
+
+    pub fn get_current_idx(&self) -> usize {
+        self.current
+    }
+
+    pub fn get_count(&self) -> usize {
+        self.tabs.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &WindowLayout> {
+        self.tabs.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut WindowLayout> {
+        self.tabs.iter_mut()
+    }
+
+    // Add a tab page after the current one and show it
+    pub fn add(&mut self, layout: WindowLayout) {
+        self.current += 1;
+        self.tabs.insert(self.current, layout);
+    }
+
+    pub fn select(&mut self, idx: usize) {
+        self.current = idx.min(self.tabs.len() - 1);
+    }
+
+    // The index of the tab page `count` places after the current one, wrapping around
+    pub fn get_relative_idx(&self, count: isize) -> usize {
+        let len = self.tabs.len() as isize;
+        (self.current as isize + count).rem_euclid(len) as usize
+    }
+
+    // Close the current tab page, showing the one after it (or before it if it was the last)
+    pub fn close_current(&mut self) -> Result<(), String> {
+        if self.tabs.len() == 1 {
+            return Err("Cannot close last tab page".into());
+        }
+        self.tabs.remove(self.current);
+        self.current = self.current.min(self.tabs.len() - 1);
+        Ok(())
+    }
+}
@@ -0,0 +1,639 @@
+use crate::editor::{
+    component::textarea::TextareaView,
+    drawing_surface::{
+        DrawingSurface,
+        layout::{Constraint, Layout},
+        sink::Sink,
+    },
+    utility::{Direction, TerminalArea, TerminalPosition, TerminalSize},
+};
+
+// A window needs a row for its text and one for its status line
+const MIN_HEIGHT: u16 = 2;
+const MIN_WIDTH: u16 = 1;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct WindowId(usize);
+
+// A view onto a buffer, windows showing the same buffer each have their own cursor and scroll
+pub struct Window {
+    pub buffer_number: usize,
+    // `None` until the window is first drawn, when it takes the buffer's last view
+    pub view: Option<TextareaView>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SplitDirection {
+    // One window above the other, as with `:split`
+    Horizontal,
+    // Side by side with a separator column in between, as with `:vsplit`
+    Vertical,
+}
+
+impl SplitDirection {
+    // How a split divides its space: the first child's share, the separator (none between
+    // windows above one another) and the rest for the second child
+    fn get_layout(self, percentage: u16) -> Layout<3> {
+        match self {
+            SplitDirection::Horizontal => Layout::rows([
+                Constraint::Percentage(percentage),
+                Constraint::Fixed(0),
+                Constraint::Fill,
+            ]),
+            SplitDirection::Vertical => Layout::columns([
+                Constraint::Percentage(percentage),
+                Constraint::Fixed(1),
+                Constraint::Fill,
+            ]),
+        }
+    }
+
+    // The space along the direction
+    fn get_length(self, size: TerminalSize) -> u16 {
+        match self {
+            SplitDirection::Horizontal => size.height,
+            SplitDirection::Vertical => size.width,
+        }
+    }
+
+    fn get_min_length(self) -> u16 {
+        match self {
+            SplitDirection::Horizontal => MIN_HEIGHT,
+            SplitDirection::Vertical => MIN_WIDTH,
+        }
+    }
+
+    // Cut the area into parts of the sizes, as a layout cuts a surface, `None` for the parts
+    // with no room
+    fn split_area(self, area: TerminalArea, sizes: [u16; 3]) -> [Option<TerminalArea>; 3] {
+        let mut start = match self {
+            SplitDirection::Horizontal => area.top,
+            SplitDirection::Vertical => area.left,
+        };
+        sizes.map(|size| {
+            let part_start = start;
+            start += size;
+            (size > 0).then(|| match self {
+                SplitDirection::Horizontal => TerminalArea {
+                    top: part_start,
+                    bottom: part_start + size - 1,
+                    ..area
+                },
+                SplitDirection::Vertical => TerminalArea {
+                    left: part_start,
+                    right: part_start + size - 1,
+                    ..area
+                },
+            })
+        })
+    }
+}
+
+// The surfaces a layout cuts the screen into
+#[derive(Default)]
+pub struct WindowSurfaces {
+    pub windows: Vec<(WindowId, Box<dyn DrawingSurface>)>,
+    // The columns between windows side by side
+    pub separators: Vec<Box<dyn DrawingSurface>>,
+    // Where the windows with room on the screen are
+    areas: Vec<(WindowId, TerminalArea)>,
+}
+
+enum LayoutNode {
+    Window(WindowId),
+    Split {
+        direction: SplitDirection,
+        first: Box<LayoutNode>,
+        second: Box<LayoutNode>,
+        // The percentage of the space the first child gets
+        percentage: u16,
+    },
+}
+
+impl LayoutNode {
+    fn contains(&self, id: WindowId) -> bool {
+        match self {
+            LayoutNode::Window(window_id) => *window_id == id,
+            LayoutNode::Split { first, second, .. } => first.contains(id) || second.contains(id),
+        }
+    }
+
+    fn get_first_window(&self) -> WindowId {
+        match self {
+            LayoutNode::Window(id) => *id,
+            LayoutNode::Split { first, .. } => first.get_first_window(),
+        }
+    }
+
+    fn collect_windows(&self, ids: &mut Vec<WindowId>) {
+        match self {
+            LayoutNode::Window(id) => ids.push(*id),
+            LayoutNode::Split { first, second, .. } => {
+                first.collect_windows(ids);
+                second.collect_windows(ids);
+            }
+        }
+    }
+
+    // The number of windows side by side in the direction
+    fn get_span(&self, direction: SplitDirection) -> usize {
+        match self {
+            LayoutNode::Window(_) => 1,
+            LayoutNode::Split {
+                direction: split_direction,
+                first,
+                second,
+                ..
+            } => {
+                let (first, second) = (first.get_span(direction), second.get_span(direction));
+                if *split_direction == direction {
+                    first + second
+                } else {
+                    first.max(second)
+                }
+            }
+        }
+    }
+
+    // The window that takes over the space of the window if it's closed
+    fn get_sibling_window(&self, id: WindowId) -> Option<WindowId> {
+        let LayoutNode::Split { first, second, .. } = self else {
+            return None;
+        };
+        match (first.as_ref(), second.as_ref()) {
+            (LayoutNode::Window(first_id), _) if *first_id == id => Some(second.get_first_window()),
+            (_, LayoutNode::Window(second_id)) if *second_id == id => {
+                Some(first.get_first_window())
+            }
+            _ => first
+                .get_sibling_window(id)
+                .or(second.get_sibling_window(id)),
+        }
+    }
+
+    fn split(&mut self, target: WindowId, new_id: WindowId, direction: SplitDirection) {
+        match self {
+            LayoutNode::Window(id) if *id == target => {
+                *self = LayoutNode::Split {
+                    direction,
+                    first: Box::new(LayoutNode::Window(new_id)),
+                    second: Box::new(LayoutNode::Window(target)),
+                    percentage: 50,
+                };
+            }
+            LayoutNode::Window(_) => {}
+            LayoutNode::Split { first, second, .. } => {
+                first.split(target, new_id, direction);
+                second.split(target, new_id, direction);
+            }
+        }
+    }
+
+    // The layout without the window, `None` if nothing is left
+    fn without(self, id: WindowId) -> Option<LayoutNode> {
+        match self {
+            LayoutNode::Window(window_id) if window_id == id => None,
+            LayoutNode::Window(_) => Some(self),
+            LayoutNode::Split {
+                direction,
+                first,
+                second,
+                percentage,
+            } => match (first.without(id), second.without(id)) {
+                (Some(first), Some(second)) => Some(LayoutNode::Split {
+                    direction,
+                    first: Box::new(first),
+                    second: Box::new(second),
+                    percentage,
+                }),
+                (Some(node), None) | (None, Some(node)) => Some(node),
+                (None, None) => None,
+            },
+        }
+    }
+
+    // Cut the surface into the windows' surfaces, and work out where they are if the surface
+    // has an area, so that drawing and looking windows up by position always agree
+    fn arrange(
+        &self,
+        surface: Box<dyn DrawingSurface>,
+        area: Option<TerminalArea>,
+        surfaces: &mut WindowSurfaces,
+    ) {
+        match self {
+            LayoutNode::Window(id) => {
+                if let Some(area) = area {
+                    surfaces.areas.push((*id, area));
+                }
+                surfaces.windows.push((*id, surface));
+            }
+            LayoutNode::Split {
+                direction,
+                first,
+                second,
+                percentage,
+            } => {
+                let layout = direction.get_layout(*percentage);
+                let [first_area, _, second_area] = match area {
+                    Some(area) => {
+                        let size = TerminalSize {
+                            width: area.get_width(),
+                            height: area.get_height(),
+                        };
+                        direction.split_area(area, layout.get_sizes(direction.get_length(size)))
+                    }
+                    None => [None; 3],
+                };
+                let [first_surface, separator, second_surface] = layout.split(surface.as_ref());
+                if *direction == SplitDirection::Vertical {
+                    surfaces.separators.push(separator);
+                }
+                first.arrange(first_surface, first_area, surfaces);
+                second.arrange(second_surface, second_area, surfaces);
+            }
+        }
+    }
+
+    // Grow (or shrink) the window by `delta` rows or columns, taking the space from the
+    // innermost split in the direction it's in
+    fn resize(
+        &mut self,
+        id: WindowId,
+        direction: SplitDirection,
+        delta: i32,
+        size: TerminalSize,
+    ) -> bool {
+        let LayoutNode::Split {
+            direction: split_direction,
+            first,
+            second,
+            percentage,
+        } = self
+        else {
+            return false;
+        };
+        let total = split_direction.get_length(size);
+        let get_sizes = |percentage: u16| split_direction.get_layout(percentage).get_sizes(total);
+        let [first_size, separator, second_size] = get_sizes(*percentage);
+        let get_child_size = |child_size: u16| match split_direction {
+            SplitDirection::Horizontal => TerminalSize {
+                height: child_size,
+                ..size
+            },
+            SplitDirection::Vertical => TerminalSize {
+                width: child_size,
+                ..size
+            },
+        };
+        let in_first = first.contains(id);
+        let resized = if in_first {
+            first.resize(id, direction, delta, get_child_size(first_size))
+        } else if second.contains(id) {
+            second.resize(id, direction, delta, get_child_size(second_size))
+        } else {
+            return false;
+        };
+        if resized || *split_direction != direction || total == 0 {
+            return resized;
+        }
+
+        // Keep both children at their minimum size where there's room for it
+        let min = split_direction.get_min_length() as i32;
+        let max = (total - separator) as i32 - min;
+        let target = if in_first {
+            first_size as i32 + delta
+        } else {
+            first_size as i32 - delta
+        };
+        let target = target.min(max).max(min.min(max)).max(0) as u16;
+        // The smallest percentage giving the first child the target size, which on a screen
+        // more than a hundred rows or columns wide may overshoot, so when shrinking the one
+        // below it is taken instead
+        let mut new_percentage = (target as u32 * 100).div_ceil(total as u32).min(100) as u16;
+        if target < first_size && get_sizes(new_percentage)[0] > target {
+            new_percentage -= 1;
+        }
+        *percentage = new_percentage;
+        true
+    }
+
+    // Give the windows side by side in each split the same size
+    fn equalize(&mut self) {
+        if let LayoutNode::Split {
+            direction,
+            first,
+            second,
+            percentage,
+        } = self
+        {
+            let (first_span, second_span) =
+                (first.get_span(*direction), second.get_span(*direction));
+            let span = first_span + second_span;
+            *percentage = ((first_span * 100 + span / 2) / span) as u16;
+            first.equalize();
+            second.equalize();
+        }
+    }
+}
+
+// The windows on the screen and how they're arranged, one of them being active
+pub struct WindowLayout {
+    windows: Vec<(WindowId, Window)>,
+    root: LayoutNode,
+    active: WindowId,
+    next_id: usize,
+}
+
+impl WindowLayout {
+    pub fn new(buffer_number: usize) -> WindowLayout {
+        let id = WindowId(0);
+        WindowLayout {
+            windows: vec![(
+                id,
+                Window {
+                    buffer_number,
+                    view: None,
+                },
+            )],
+            root: LayoutNode::Window(id),
+            active: id,
+            next_id: 1,
+        }
+    }
+
+    pub fn get_active_id(&self) -> WindowId {
+        self.active
+    }
+
+    pub fn set_active(&mut self, id: WindowId) {
+        if self.windows.iter().any(|(window_id, _)| *window_id == id) {
+            self.active = id;
+        }
+    }
+
+    pub fn get_active(&self) -> &Window {
+        self.get(self.active)
+    }
+
+    pub fn get_active_mut(&mut self) -> &mut Window {
+        self.get_mut(self.active)
+    }
+
+    pub fn get(&self, id: WindowId) -> &Window {
+        let (_, window) = self
+            .windows
+            .iter()
+            .find(|(window_id, _)| *window_id == id)
+            .expect("window ids come from the layout");
+        window
+    }
+
+    pub fn get_mut(&mut self, id: WindowId) -> &mut Window {
+        let (_, window) = self
+            .windows
+            .iter_mut()
+            .find(|(window_id, _)| *window_id == id)
+            .expect("window ids come from the layout");
+        window
+    }
+
+    pub fn get_count(&self) -> usize {
+        self.windows.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Window> {
+        self.windows.iter().map(|(_, window)| window)
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Window> {
+        self.windows.iter_mut().map(|(_, window)| window)
+    }
+
+    // The windows from top-left to bottom-right
+    pub fn get_window_ids(&self) -> Vec<WindowId> {
+        let mut ids = vec![];
+        self.root.collect_windows(&mut ids);
+        ids
+    }
+
+    // The window `count` places after the active one, wrapping around
+    pub fn get_relative_window(&self, count: isize) -> WindowId {
+        let ids = self.get_window_ids();
+        let idx = ids.iter().position(|id| *id == self.active).unwrap_or(0);
+        ids[(idx as isize + count).rem_euclid(ids.len() as isize) as usize]
+    }
+
+    // Split the active window in two, the new half showing the same buffer and becoming active
+    pub fn split(&mut self, direction: SplitDirection, size: TerminalSize) -> Result<(), String> {
+        let areas = self.get_areas(size);
+        let Some((_, area)) = areas.iter().find(|(id, _)| *id == self.active) else {
+            return Err("Not enough room".into());
+        };
+        let has_room = match direction {
+            SplitDirection::Horizontal => area.get_height() >= 2 * MIN_HEIGHT,
+            SplitDirection::Vertical => area.get_width() > 2 * MIN_WIDTH,
+        };
+        if !has_room {
+            return Err("Not enough room".into());
+        }
+
+        let id = WindowId(self.next_id);
+        self.next_id += 1;
+        let active = self.get_active();
+        let window = Window {
+            buffer_number: active.buffer_number,
+            view: active.view,
+        };
+        self.windows.push((id, window));
+        self.root.split(self.active, id, direction);
+        self.active = id;
+        Ok(())
+    }
+
+    pub fn close(&mut self, id: WindowId) -> Result<(), String> {
+        if self.windows.len() == 1 {
+            return Err("Cannot close last window".into());
+        }
+        let sibling = self.root.get_sibling_window(id);
+        let root = std::mem::replace(&mut self.root, LayoutNode::Window(id));
+        if let Some(root) = root.without(id) {
+            self.root = root;
+        }
+        self.windows.retain(|(window_id, _)| *window_id != id);
+        if self.active == id {
+            self.active = sibling.unwrap_or(self.root.get_first_window());
+        }
+        Ok(())
+    }
+
+    // Close every window but the active one
+    pub fn only(&mut self) {
+        self.root = LayoutNode::Window(self.active);
+        self.windows.retain(|(id, _)| *id == self.active);
+    }
+
+    // Lay the windows out on a surface of the size
+    fn arrange(&self, surface: Box<dyn DrawingSurface>, size: TerminalSize) -> WindowSurfaces {
+        let mut surfaces = WindowSurfaces::default();
+        let area = (size.width > 0 && size.height > 0)
+            .then(|| TerminalArea::new(TerminalPosition::default(), size));
+        self.root.arrange(surface, area, &mut surfaces);
+        surfaces
+    }
+
+    // Where each window is on a screen of the size
+    fn get_areas(&self, size: TerminalSize) -> Vec<(WindowId, TerminalArea)> {
+        self.arrange(Box::new(Sink), size).areas
+    }
+
+    // The window next to the active one in the direction, the closest to its top-left corner
+    // if there are several
+    pub fn find_neighbor(&self, direction: Direction, size: TerminalSize) -> Option<WindowId> {
+        let areas = self.get_areas(size);
+        let (_, current) = *areas.iter().find(|(id, _)| *id == self.active)?;
+        let overlaps_rows = |area: &TerminalArea| {
+            area.get_top() <= current.get_bottom() && area.get_bottom() >= current.get_top()
+        };
+        let overlaps_cols = |area: &TerminalArea| {
+            area.get_left() <= current.get_right() && area.get_right() >= current.get_left()
+        };
+        areas
+            .iter()
+            .filter_map(|(id, area)| {
+                let distance = match direction {
+                    Direction::Left
+                        if area.get_right() < current.get_left() && overlaps_rows(area) =>
+                    {
+                        current.get_left() - area.get_right()
+                    }
+                    Direction::Right
+                        if area.get_left() > current.get_right() && overlaps_rows(area) =>
+                    {
+                        area.get_left() - current.get_right()
+                    }
+                    Direction::Up
+                        if area.get_bottom() < current.get_top() && overlaps_cols(area) =>
+                    {
+                        current.get_top() - area.get_bottom()
+                    }
+                    Direction::Down
+                        if area.get_top() > current.get_bottom() && overlaps_cols(area) =>
+                    {
+                        area.get_top() - current.get_bottom()
+                    }
+                    _ => return None,
+                };
+                let offset = match direction {
+                    Direction::Left | Direction::Right => {
+                        area.get_top().abs_diff(current.get_top())
+                    }
+                    Direction::Up | Direction::Down => area.get_left().abs_diff(current.get_left()),
+                };
+                Some(((distance, offset), *id))
+            })
+            .min_by_key(|(key, _)| *key)
+            .map(|(_, id)| id)
+    }
+
+    pub fn resize(&mut self, direction: SplitDirection, delta: i32, size: TerminalSize) {
+        self.root.resize(self.active, direction, delta, size);
+    }
+
+    pub fn equalize(&mut self) {
+        self.root.equalize();
+    }
+
+    // Cut the surface into one surface per window, and the separator columns between them
+    pub fn slice(&self, surface: Box<dyn DrawingSurface>) -> WindowSurfaces {
+        let size = surface.get_bounding_rect_size();
+        self.arrange(surface, size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: TerminalSize = TerminalSize {
+        width: 80,
+        height: 24,
+    };
+
+    // The top, left, bottom and right of the window
+    fn get_area(layout: &WindowLayout, id: usize) -> Option<(u16, u16, u16, u16)> {
+        layout
+            .get_areas(SIZE)
+            .into_iter()
+            .find(|(window_id, _)| *window_id == WindowId(id))
+            .map(|(_, area)| (area.top, area.left, area.bottom, area.right))
+    }
+
+    #[test]
+    fn split_halves_the_active_window() {
+        let mut layout = WindowLayout::new(1);
+        layout.split(SplitDirection::Vertical, SIZE).unwrap();
+        // The new window goes to the left of (or above) the one split
+        assert_eq!(get_area(&layout, 1), Some((0, 0, 23, 39)));
+        assert_eq!(get_area(&layout, 0), Some((0, 41, 23, 79)));
+        assert!(layout.get_active_id() == WindowId(1));
+
+        layout.split(SplitDirection::Horizontal, SIZE).unwrap();
+        assert_eq!(get_area(&layout, 2), Some((0, 0, 11, 39)));
+        assert_eq!(get_area(&layout, 1), Some((12, 0, 23, 39)));
+        let surfaces = layout.slice(Box::new(Sink));
+        assert_eq!((surfaces.windows.len(), surfaces.separators.len()), (3, 1));
+    }
+
+    #[test]
+    fn split_needs_room_for_both_windows() {
+        let mut layout = WindowLayout::new(1);
+        let size = TerminalSize {
+            width: 2,
+            height: 3,
+        };
+        assert!(layout.split(SplitDirection::Horizontal, size).is_err());
+        assert!(layout.split(SplitDirection::Vertical, size).is_err());
+        assert_eq!(layout.get_count(), 1);
+    }
+
+    #[test]
+    fn close_activates_the_sibling() {
+        let mut layout = WindowLayout::new(1);
+        layout.split(SplitDirection::Vertical, SIZE).unwrap();
+        layout.split(SplitDirection::Horizontal, SIZE).unwrap();
+        layout.close(WindowId(2)).unwrap();
+        assert!(layout.get_active_id() == WindowId(1));
+        assert_eq!(get_area(&layout, 1), Some((0, 0, 23, 39)));
+        layout.close(WindowId(1)).unwrap();
+        assert_eq!(get_area(&layout, 0), Some((0, 0, 23, 79)));
+        assert!(layout.close(WindowId(0)).is_err());
+    }
+
+    #[test]
+    fn find_neighbor_follows_the_areas() {
+        let mut layout = WindowLayout::new(1);
+        layout.split(SplitDirection::Vertical, SIZE).unwrap();
+        layout.split(SplitDirection::Horizontal, SIZE).unwrap();
+        assert!(layout.find_neighbor(Direction::Down, SIZE) == Some(WindowId(1)));
+        assert!(layout.find_neighbor(Direction::Right, SIZE) == Some(WindowId(0)));
+        assert!(layout.find_neighbor(Direction::Up, SIZE).is_none());
+        layout.set_active(WindowId(0));
+        // The closest to the top-left corner of the windows to the left
+        assert!(layout.find_neighbor(Direction::Left, SIZE) == Some(WindowId(2)));
+    }
+
+    #[test]
+    fn resize_moves_the_split_and_equalize_undoes_it() {
+        let mut layout = WindowLayout::new(1);
+        layout.split(SplitDirection::Vertical, SIZE).unwrap();
+        layout.resize(SplitDirection::Vertical, 2, SIZE);
+        assert_eq!(get_area(&layout, 1), Some((0, 0, 23, 41)));
+        // Resizing in the other direction has no split to move
+        layout.resize(SplitDirection::Horizontal, 2, SIZE);
+        assert_eq!(get_area(&layout, 1), Some((0, 0, 23, 41)));
+        layout.resize(SplitDirection::Vertical, -100, SIZE);
+        assert_eq!(get_area(&layout, 1), Some((0, 0, 23, 0)));
+        layout.equalize();
+        assert_eq!(get_area(&layout, 0), Some((0, 41, 23, 79)));
+    }
+}
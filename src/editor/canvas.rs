@@ -1,27 +1,54 @@
 use crate::editor::{
+    color::ColorDepth,
     terminal::Terminal,
-    utility::{Style, TerminalPosition},
+    theme::{HighlightGroup, Theme},
+    utility::{CursorShape, Style, TerminalArea, TerminalPosition},
+    width::{self, DisplayWidth, WidthModel},
 };
-use std::{cmp::max, io::Error};
+use std::{cmp::max, io::Error, mem::discriminant, rc::Rc};
 use unicode_segmentation::UnicodeSegmentation;
-use unicode_width::UnicodeWidthStr;
 
 use super::utility::TerminalSize;
 
+// A column of a row on the terminal
 #[derive(Default, PartialEq, Eq, Clone)]
-struct ContentSegment {
-    content: String,
-    offset: u16,
+enum Cell {
+    #[default]
+    Blank,
+    Grapheme(String),
+    // The trailing column of the double-width grapheme to its left
+    Continuation,
 }
 
 #[derive(Default, PartialEq, Eq, Clone)]
 struct ContentLine {
-    segments: Vec<ContentSegment>,
+    cells: Vec<Cell>,
 }
 
-#[derive(Default, PartialEq, Eq, Clone)]
+impl ContentLine {
+    // Put a cell in a column, blanking the other half of a double-width grapheme it overwrites,
+    // as half of a grapheme can't be displayed
+    fn set(&mut self, col: usize, cell: Cell) {
+        if self.cells.len() <= col {
+            self.cells.resize(col + 1, Cell::Blank);
+        }
+        match &self.cells[col] {
+            Cell::Continuation if col > 0 => self.cells[col - 1] = Cell::Blank,
+            Cell::Grapheme(grapheme) if grapheme.display_width() > 1 => {
+                if let Some(next) = self.cells.get_mut(col + 1) {
+                    *next = Cell::Blank;
+                }
+            }
+            _ => {}
+        }
+        self.cells[col] = cell;
+    }
+}
+
+#[derive(PartialEq, Eq, Clone)]
 struct StyleSegment {
-    styles: Vec<Style>,
+    group: HighlightGroup,
+    priority: u8,
     start_col: u16,
     end_col: u16,
 }
@@ -31,12 +58,70 @@ struct StyleLine {
     segments: Vec<StyleSegment>,
 }
 
+impl StyleLine {
+    // Remove the highlights from the columns between `start_col` and `end_col` (exclusive),
+    // keeping the parts of those that stretch beyond them
+    fn cut(&mut self, start_col: u16, end_col: u16) {
+        let mut segments = vec![];
+        for segment in self.segments.drain(..) {
+            if segment.end_col <= start_col || segment.start_col >= end_col {
+                segments.push(segment);
+                continue;
+            }
+            if segment.start_col < start_col {
+                segments.push(StyleSegment {
+                    end_col: start_col,
+                    ..segment.clone()
+                });
+            }
+            if segment.end_col > end_col {
+                segments.push(StyleSegment {
+                    start_col: end_col,
+                    ..segment
+                });
+            }
+        }
+        self.segments = segments;
+    }
+}
+
+// An overlay drawn over the canvas and the layers added before it, e.g. for a popup
+// Every cell of its area hides the cell beneath it, even the ones left blank
+struct Layer {
+    area: TerminalArea,
+    lines: Vec<ContentLine>,
+    style_lines: Vec<StyleLine>,
+}
+
+// The layer that content is drawn on, the layers being stacked in the order they were added
+#[derive(Clone, Copy)]
+pub struct LayerId(usize);
+
+// A shift of the rows between `top` and `bottom` (inclusive) by `distance` rows,
+// the rows shifted out of the region are discarded and the exposed rows are blank
+struct Scroll {
+    top: u16,
+    bottom: u16,
+    distance: u16,
+    upwards: bool,
+}
+
 pub struct Canvas {
     prev_lines: Vec<ContentLine>,
     lines: Vec<ContentLine>,
     prev_style_lines: Vec<StyleLine>,
     style_lines: Vec<StyleLine>,
+    // Drawn over the lines above when rendering, which leaves the lines as they appear on screen
+    layers: Vec<Layer>,
     cursors: Vec<TerminalPosition>,
+    prev_cursor_shape: Option<CursorShape>,
+    cursor_shape: CursorShape,
+    theme: Rc<Theme>,
+    color_depth: Option<ColorDepth>,
+    width_model: WidthModel,
+    // Set when the theme, the color depth or the width model is changed, as every styled cell
+    // may then look different
+    full_render_pending: bool,
 }
 
 impl Canvas {
@@ -46,67 +131,157 @@ impl Canvas {
             lines: vec![],
             prev_style_lines: vec![],
             style_lines: vec![],
+            layers: vec![],
             cursors: vec![],
+            prev_cursor_shape: None,
+            cursor_shape: CursorShape::default(),
+            theme: Rc::new(Theme::default()),
+            color_depth: None,
+            width_model: WidthModel::default(),
+            full_render_pending: false,
         }
     }
 
     pub fn get_size(&self) -> TerminalSize {
-        Terminal::get_size().unwrap_or(TerminalSize::default())
+        Terminal::get_size().unwrap_or_default()
+    }
+
+    pub fn set_theme(&mut self, theme: Rc<Theme>) {
+        if !Rc::ptr_eq(&self.theme, &theme) {
+            self.theme = theme;
+            self.full_render_pending = true;
+        }
     }
 
-    pub fn add_styles(
+    // Render with the given color depth rather than the detected one, `None` to use the
+    // detected one
+    pub fn set_color_depth(&mut self, color_depth: Option<ColorDepth>) {
+        if self.color_depth != color_depth {
+            self.color_depth = color_depth;
+            Terminal::override_color_depth(color_depth);
+            self.full_render_pending = true;
+        }
+    }
+
+    pub fn set_width_model(&mut self, width_model: WidthModel) {
+        if self.width_model != width_model {
+            self.width_model = width_model;
+            width::set_width_model(width_model);
+            self.full_render_pending = true;
+        }
+    }
+
+    // Add a layer over everything drawn so far, which is only drawn on within its area
+    pub fn add_layer(&mut self, area: TerminalArea) -> LayerId {
+        self.layers.push(Layer {
+            area,
+            lines: vec![],
+            style_lines: vec![],
+        });
+        LayerId(self.layers.len() - 1)
+    }
+
+    // The lines to draw on, those of the layer if one is given
+    fn get_lines_mut(
+        &mut self,
+        layer: Option<LayerId>,
+    ) -> (&mut Vec<ContentLine>, &mut Vec<StyleLine>) {
+        match layer.and_then(|LayerId(idx)| self.layers.get_mut(idx)) {
+            Some(layer) => (&mut layer.lines, &mut layer.style_lines),
+            None => (&mut self.lines, &mut self.style_lines),
+        }
+    }
+
+    pub fn add_highlight(
         &mut self,
-        styles: Vec<Style>,
+        layer: Option<LayerId>,
+        group: HighlightGroup,
+        priority: Option<u8>,
         start: TerminalPosition,
         end: TerminalPosition,
     ) {
+        let width = self.get_size().width;
+        let (_, style_lines) = self.get_lines_mut(layer);
         let max_row = max(start.row, end.row) as usize;
-        while self.style_lines.len() <= max_row {
-            self.style_lines.push(StyleLine::default());
+        while style_lines.len() <= max_row {
+            style_lines.push(StyleLine::default());
         }
 
         for row in start.row..=end.row {
             let row_idx = row as usize;
 
             let start_col = if row == start.row { start.col } else { 0 };
-            let end_col = if row == end.row {
-                end.col
-            } else {
-                self.get_size().width
-            };
+            let end_col = if row == end.row { end.col } else { width };
 
             if start_col >= end_col {
                 continue;
             }
 
             let style_segment = StyleSegment {
-                styles: styles.clone(),
+                group,
+                priority: priority.unwrap_or(group.get_priority()),
                 start_col,
                 end_col,
             };
 
-            self.style_lines[row_idx].segments.push(style_segment);
+            style_lines[row_idx].segments.push(style_segment);
         }
     }
 
-    pub fn add_content(&mut self, content: &str, origin: TerminalPosition) {
-        while self.lines.len() <= origin.row as usize {
-            self.lines.push(ContentLine::default());
+    // Write the content from `origin`, clipped before the column `end_col`
+    // A double-width grapheme that doesn't fit is replaced by blanks
+    pub fn add_content(
+        &mut self,
+        layer: Option<LayerId>,
+        content: &str,
+        origin: TerminalPosition,
+        end_col: u16,
+    ) {
+        let end_col = end_col.min(self.get_size().width) as usize;
+        let (lines, _) = self.get_lines_mut(layer);
+        while lines.len() <= origin.row as usize {
+            lines.push(ContentLine::default());
+        }
+        let line = &mut lines[origin.row as usize];
+        let mut col = origin.col as usize;
+        for grapheme in content.graphemes(true) {
+            let width = grapheme.display_width();
+            // A zero-width grapheme can't be given a cell of its own
+            if width == 0 {
+                continue;
+            }
+            if col + width > end_col {
+                for blank_col in col..end_col {
+                    line.set(blank_col, Cell::Blank);
+                }
+                break;
+            }
+            line.set(col, Cell::Grapheme(grapheme.into()));
+            for continuation_col in col + 1..col + width {
+                line.set(continuation_col, Cell::Continuation);
+            }
+            col += width;
         }
-        self.lines[origin.row as usize]
-            .segments
-            .push(ContentSegment {
-                content: content.into(),
-                offset: origin.col,
-            });
     }
 
     pub fn add_cursor(&mut self, position: TerminalPosition) {
         self.cursors.push(position);
     }
 
+    pub fn set_cursor_shape(&mut self, shape: CursorShape) {
+        self.cursor_shape = shape;
+    }
+
     pub fn render_changes(&mut self) -> Result<(), Error> {
+        if self.full_render_pending {
+            return self.render_all();
+        }
+        self.merge_layers();
+        Terminal::begin_synchronized_update()?;
         Terminal::hide_cursor()?;
+        if let Some(scroll) = self.find_scroll() {
+            self.scroll(&scroll)?;
+        }
         for i in 0..max(self.lines.len(), self.prev_lines.len()) as usize {
             let cur_line = self.lines.get(i);
             let prev_line = self.prev_lines.get(i);
@@ -117,12 +292,19 @@ impl Canvas {
             }
         }
         Terminal::show_cursor()?;
+        if self.prev_cursor_shape != Some(self.cursor_shape) {
+            Terminal::set_cursor_shape(self.cursor_shape)?;
+        }
         self.render_cursor()?;
+        Terminal::end_synchronized_update()?;
         Terminal::flush()?;
         Ok(())
     }
 
     pub fn render_all(&mut self) -> Result<(), Error> {
+        self.full_render_pending = false;
+        self.merge_layers();
+        Terminal::begin_synchronized_update()?;
         Terminal::hide_cursor()?;
         for i in 0..max(self.lines.len(), self.prev_lines.len()) as usize {
             let cur_line = self.lines.get(i);
@@ -130,7 +312,9 @@ impl Canvas {
             self.render_line(i as u16, cur_line, cur_style)?;
         }
         Terminal::show_cursor()?;
+        Terminal::set_cursor_shape(self.cursor_shape)?;
         self.render_cursor()?;
+        Terminal::end_synchronized_update()?;
         Terminal::flush()?;
         Ok(())
     }
@@ -138,11 +322,154 @@ impl Canvas {
     pub fn clear(&mut self) {
         self.prev_lines = self.lines.clone();
         self.prev_style_lines = self.style_lines.clone();
+        self.prev_cursor_shape = Some(self.cursor_shape);
         self.lines.clear();
         self.style_lines.clear();
+        self.layers.clear();
         self.cursors.clear();
     }
 
+    // Draw the layers over the lines in order, each replacing the content and highlights of its
+    // area
+    // As the previous frame is kept merged too, the cells a layer no longer covers are redrawn
+    fn merge_layers(&mut self) {
+        for layer in std::mem::take(&mut self.layers) {
+            let area = layer.area;
+            let bottom = area.get_bottom() as usize;
+            while self.lines.len() <= bottom {
+                self.lines.push(ContentLine::default());
+            }
+            while self.style_lines.len() <= bottom {
+                self.style_lines.push(StyleLine::default());
+            }
+            for row in area.get_top() as usize..=bottom {
+                let layer_line = layer.lines.get(row);
+                for col in area.get_left() as usize..=area.get_right() as usize {
+                    let cell = layer_line
+                        .and_then(|line| line.cells.get(col))
+                        .cloned()
+                        .unwrap_or_default();
+                    self.lines[row].set(col, cell);
+                }
+
+                let end_col = area.get_right() + 1;
+                let style_line = &mut self.style_lines[row];
+                style_line.cut(area.get_left(), end_col);
+                let Some(layer_style_line) = layer.style_lines.get(row) else {
+                    continue;
+                };
+                for segment in &layer_style_line.segments {
+                    let start_col = segment.start_col.max(area.get_left());
+                    let segment_end_col = segment.end_col.min(end_col);
+                    if start_col < segment_end_col {
+                        style_line.segments.push(StyleSegment {
+                            start_col,
+                            end_col: segment_end_col,
+                            ..segment.clone()
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    // Find the scroll that makes the most rows of the previous frame line up with the current one,
+    // i.e. a contiguous run of rows that are all shifted copies of the previous rows by the same
+    // distance
+    fn find_scroll(&self) -> Option<Scroll> {
+        let row_count = max(self.lines.len(), self.prev_lines.len());
+        let empty_line = ContentLine::default();
+        let empty_style = StyleLine::default();
+        let same_row = |cur_idx: usize, prev_idx: usize| {
+            self.lines.get(cur_idx).unwrap_or(&empty_line)
+                == self.prev_lines.get(prev_idx).unwrap_or(&empty_line)
+                && self.style_lines.get(cur_idx).unwrap_or(&empty_style)
+                    == self.prev_style_lines.get(prev_idx).unwrap_or(&empty_style)
+        };
+
+        let mut best_scroll = None;
+        let mut best_saved_rows = 0;
+        for distance in 1..row_count {
+            for upwards in [true, false] {
+                // The current row `row + distance` was the previous row `row` when scrolling down
+                let row_pair = |row: usize| {
+                    if upwards {
+                        (row, row + distance)
+                    } else {
+                        (row + distance, row)
+                    }
+                };
+                let mut run_start = None;
+                let mut saved_rows = 0;
+                for row in 0..=row_count - distance {
+                    let (cur_idx, prev_idx) = row_pair(row);
+                    if row < row_count - distance && same_row(cur_idx, prev_idx) {
+                        run_start.get_or_insert(row);
+                        // Rows that didn't change anyway are not saved by scrolling
+                        if !same_row(cur_idx, cur_idx) {
+                            saved_rows += 1;
+                        }
+                        continue;
+                    }
+                    if let Some(start) = run_start.take() {
+                        if saved_rows > best_saved_rows {
+                            best_saved_rows = saved_rows;
+                            best_scroll = Some(Scroll {
+                                top: start as u16,
+                                bottom: (row - 1 + distance) as u16,
+                                distance: distance as u16,
+                                upwards,
+                            });
+                        }
+                        saved_rows = 0;
+                    }
+                }
+            }
+        }
+        best_scroll
+    }
+
+    // Scroll the rows on the terminal, then mirror the scroll in the previous frame,
+    // so that only the exposed rows are found to have changed
+    fn scroll(&mut self, scroll: &Scroll) -> Result<(), Error> {
+        // The exposed rows are filled with the current background, which must be the default one
+        self.reset_all_styles()?;
+        Terminal::set_scroll_region(scroll.top, scroll.bottom)?;
+        if scroll.upwards {
+            Terminal::scroll_up(scroll.distance)?;
+        } else {
+            Terminal::scroll_down(scroll.distance)?;
+        }
+        Terminal::reset_scroll_region()?;
+
+        let top = scroll.top as usize;
+        let bottom = scroll.bottom as usize;
+        let distance = scroll.distance as usize;
+        while self.prev_lines.len() <= bottom {
+            self.prev_lines.push(ContentLine::default());
+        }
+        while self.prev_style_lines.len() <= bottom {
+            self.prev_style_lines.push(StyleLine::default());
+        }
+        let lines = &mut self.prev_lines[top..=bottom];
+        let style_lines = &mut self.prev_style_lines[top..=bottom];
+        if scroll.upwards {
+            lines.rotate_left(distance);
+            style_lines.rotate_left(distance);
+        } else {
+            lines.rotate_right(distance);
+            style_lines.rotate_right(distance);
+        }
+        let exposed = if scroll.upwards {
+            lines.len() - distance..lines.len()
+        } else {
+            0..distance
+        };
+        lines[exposed.clone()].fill(ContentLine::default());
+        style_lines[exposed].fill(StyleLine::default());
+        Ok(())
+    }
+
     fn render_line(
         &self,
         line_idx: u16,
@@ -153,7 +480,7 @@ impl Canvas {
 
         Terminal::move_to(TerminalPosition {
             col: 0,
-            row: line_idx as u16,
+            row: line_idx,
         })?;
         Terminal::clear_line()?;
 
@@ -162,66 +489,157 @@ impl Canvas {
         let default_style_line = StyleLine::default();
         let style_line = style.unwrap_or(&default_style_line);
 
-        for offset in 0..self.get_size().width {
-            let applicable_styles = self.find_applicable_styles(style_line, offset);
-            for style_to_apply in applicable_styles {
-                Terminal::set_style(style_to_apply)?;
-            }
-            Terminal::print(" ")?;
-        }
-
-        for segment in &content_line.segments {
-            Terminal::move_to(TerminalPosition {
-                col: segment.offset,
-                row: line_idx as u16,
-            })?;
-            let mut cur_offset = segment.offset;
-
-            for grapheme in segment.content.graphemes(true) {
-                let applicable_styles = self.find_applicable_styles(style_line, cur_offset);
-                for style_to_apply in applicable_styles {
-                    Terminal::set_style(style_to_apply)?;
+        // Styles are only sent when they differ from the previous cell's
+        let mut cur_styles = self.resolve_styles(&StyleLine::default(), 0);
+        let mut apply_styles = |styles: Vec<Style>| -> Result<(), Error> {
+            if styles != cur_styles {
+                Terminal::reset_style()?;
+                for style in &styles {
+                    Terminal::set_style(style.clone())?;
                 }
+                cur_styles = styles;
+            }
+            Ok(())
+        };
 
-                Terminal::print(grapheme)?;
-                cur_offset += grapheme.width() as u16;
+        for col in 0..self.get_size().width {
+            apply_styles(self.resolve_styles(style_line, col))?;
+            match content_line.cells.get(col as usize) {
+                Some(Cell::Grapheme(grapheme)) => Terminal::print(grapheme)?,
+                // Already covered by the double-width grapheme printed before it
+                Some(Cell::Continuation) => {}
+                _ => Terminal::print(" ")?,
             }
         }
         Ok(())
     }
 
+    // The terminal has a single cursor, so where several are placed the last one wins, e.g. a
+    // prompt's over the window beneath it
     fn render_cursor(&self) -> Result<(), Error> {
-        if self.cursors.len() == 0 {
-            return Ok(());
+        if let Some(position) = self.cursors.last() {
+            Terminal::move_to(*position)?;
         }
-        if self.cursors.len() > 1 {
-            todo!("Not implemented yet");
-        }
-        Terminal::move_to(self.cursors[0])?;
         Ok(())
     }
 
     fn reset_all_styles(&self) -> Result<(), Error> {
-        Terminal::set_style(Style::Bold(false))?;
-        Terminal::set_style(Style::Italic(false))?;
-        Terminal::set_style(Style::Underlined(false))?;
-        Terminal::set_style(Style::Inverted(false))?;
-        Terminal::set_style(Style::Foreground(crossterm::style::Color::White))?;
-        Terminal::set_style(Style::Background(crossterm::style::Color::Black))?;
+        Terminal::reset_style()?;
+        for style in self.theme.get_styles(HighlightGroup::Normal) {
+            Terminal::set_style(style.clone())?;
+        }
         Ok(())
     }
 
-    fn find_applicable_styles(&self, style_line: &StyleLine, col: u16) -> Vec<Style> {
-        let mut applicable_styles = Vec::new();
+    // Merge the styles of the highlights covering the cell in order of priority,
+    // keeping one style per attribute so that higher priorities override only what they set
+    // Highlights with the same priority are applied in the order they were added
+    fn resolve_styles(&self, style_line: &StyleLine, col: u16) -> Vec<Style> {
+        let mut segments: Vec<&StyleSegment> = style_line
+            .segments
+            .iter()
+            .filter(|segment| col >= segment.start_col && col < segment.end_col)
+            .collect();
+        segments.sort_by_key(|segment| segment.priority);
 
-        for style_segment in &style_line.segments {
-            if col >= style_segment.start_col && col < style_segment.end_col {
-                for style in &style_segment.styles {
-                    applicable_styles.push(style.clone());
-                }
+        let mut styles: Vec<Style> = Vec::new();
+        let groups = std::iter::once(HighlightGroup::Normal)
+            .chain(segments.iter().map(|segment| segment.group));
+        for group in groups {
+            for style in self.theme.get_styles(group) {
+                styles.retain(|applied| discriminant(applied) != discriminant(style));
+                styles.push(style.clone());
+            }
+        }
+        styles
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A line with the graphemes in consecutive cells, a continuation after each wide one
+    fn get_line(text: &str) -> ContentLine {
+        let mut line = ContentLine::default();
+        let mut col = 0;
+        for grapheme in text.graphemes(true) {
+            line.set(col, Cell::Grapheme(grapheme.into()));
+            for continuation_col in col + 1..col + grapheme.display_width() {
+                line.set(continuation_col, Cell::Continuation);
             }
+            col += grapheme.display_width();
         }
+        line
+    }
+
+    #[test]
+    fn overwriting_half_a_wide_grapheme_blanks_the_other_half() {
+        let mut line = get_line("a\u{4e00}b");
+        line.set(2, Cell::Grapheme("x".into()));
+        assert!(line.cells[1] == Cell::Blank && line.cells[2] == Cell::Grapheme("x".into()));
+
+        let mut line = get_line("a\u{4e00}b");
+        line.set(1, Cell::Grapheme("x".into()));
+        assert!(line.cells[1] == Cell::Grapheme("x".into()) && line.cells[2] == Cell::Blank);
+        assert!(line.cells[3] == Cell::Grapheme("b".into()));
+    }
+
+    #[test]
+    fn a_layer_edge_through_a_wide_grapheme_blanks_the_cut_off_half() {
+        let mut canvas = Canvas::new();
+        canvas.lines = vec![get_line("\u{4e00}\u{4e00}\u{4e00}")];
+        // The layer covers the second half of the first grapheme and the first half of the last
+        canvas.add_layer(TerminalArea {
+            top: 0,
+            left: 1,
+            bottom: 0,
+            right: 4,
+        });
+        canvas.merge_layers();
+        assert!(
+            canvas.lines[0]
+                .cells
+                .iter()
+                .all(|cell| *cell == Cell::Blank)
+        );
+    }
+
+    fn get_lines(texts: &[&str]) -> Vec<ContentLine> {
+        texts
+            .iter()
+            .map(|text| ContentLine {
+                cells: vec![Cell::Grapheme(text.to_string())],
+            })
+            .collect()
+    }
+
+    fn find_scroll(prev_texts: &[&str], texts: &[&str]) -> Option<(u16, u16, u16, bool)> {
+        let mut canvas = Canvas::new();
+        canvas.prev_lines = get_lines(prev_texts);
+        canvas.lines = get_lines(texts);
+        canvas
+            .find_scroll()
+            .map(|scroll| (scroll.top, scroll.bottom, scroll.distance, scroll.upwards))
+    }
+
+    #[test]
+    fn find_scroll_lines_up_shifted_rows() {
+        let prev = ["a", "b", "c", "d", "e"];
+        assert_eq!(
+            find_scroll(&prev, &["b", "c", "d", "e", "f"]),
+            Some((0, 4, 1, true))
+        );
+        assert_eq!(
+            find_scroll(&prev, &["x", "y", "a", "b", "c"]),
+            Some((0, 4, 2, false))
+        );
+    }
 
-        applicable_styles
+    #[test]
+    fn find_scroll_skips_rows_that_did_not_change() {
+        let prev = ["a", "b", "c", "d", "e"];
+        assert_eq!(find_scroll(&prev, &prev), None);
+        assert_eq!(find_scroll(&prev, &["a", "x", "c", "d", "e"]), None);
     }
 }
@@ -0,0 +1,205 @@
+use crossterm::style::Color;
+
+// How many colors the terminal can display
+#[derive(Default, PartialEq, Eq, Clone, Copy)]
+pub enum ColorDepth {
+    TrueColor,
+    Ansi256,
+    #[default]
+    Ansi16,
+}
+
+// The 16 ANSI colors as xterm displays them, in palette order
+const ANSI_16_PALETTE: [(Color, (u8, u8, u8)); 16] = [
+    (Color::Black, (0, 0, 0)),
+    (Color::DarkRed, (205, 0, 0)),
+    (Color::DarkGreen, (0, 205, 0)),
+    (Color::DarkYellow, (205, 205, 0)),
+    (Color::DarkBlue, (0, 0, 238)),
+    (Color::DarkMagenta, (205, 0, 205)),
+    (Color::DarkCyan, (0, 205, 205)),
+    (Color::Grey, (229, 229, 229)),
+    (Color::DarkGrey, (127, 127, 127)),
+    (Color::Red, (255, 0, 0)),
+    (Color::Green, (0, 255, 0)),
+    (Color::Yellow, (255, 255, 0)),
+    (Color::Blue, (92, 92, 255)),
+    (Color::Magenta, (255, 0, 255)),
+    (Color::Cyan, (0, 255, 255)),
+    (Color::White, (255, 255, 255)),
+];
+
+// The levels of each channel in the 6x6x6 color cube of the 256-color palette
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+impl ColorDepth {
+    pub fn parse(name: &str) -> Option<ColorDepth> {
+        match name {
+            "truecolor" | "24bit" => Some(ColorDepth::TrueColor),
+            "256" => Some(ColorDepth::Ansi256),
+            "16" => Some(ColorDepth::Ansi16),
+            _ => None,
+        }
+    }
+
+    // Guess the color depth from the environment, the same way most terminal programs do
+    pub fn detect() -> ColorDepth {
+        let colorterm = std::env::var("COLORTERM").unwrap_or_default();
+        if colorterm == "truecolor" || colorterm == "24bit" {
+            return ColorDepth::TrueColor;
+        }
+        let term = std::env::var("TERM").unwrap_or_default();
+        if term.ends_with("-direct") {
+            ColorDepth::TrueColor
+        } else if term.contains("256color") {
+            ColorDepth::Ansi256
+        } else {
+            ColorDepth::Ansi16
+        }
+    }
+
+    // Map a color to the nearest one the terminal can display
+    pub fn quantize(&self, color: Color) -> Color {
+        match (self, color) {
+            (ColorDepth::TrueColor, _) => color,
+            (ColorDepth::Ansi256, Color::Rgb { r, g, b }) => {
+                Color::AnsiValue(nearest_256((r, g, b)))
+            }
+            (ColorDepth::Ansi16, Color::Rgb { r, g, b }) => nearest_16((r, g, b)),
+            (ColorDepth::Ansi16, Color::AnsiValue(value)) if value >= 16 => {
+                nearest_16(ansi_256_to_rgb(value))
+            }
+            (ColorDepth::Ansi16, Color::AnsiValue(value)) => ANSI_16_PALETTE[value as usize].0,
+            _ => color,
+        }
+    }
+}
+
+// The SGR parameter selecting one of the 16 ANSI colors, as understood by terminals
+// that can't handle the 256-color `38;5;n` form
+pub fn ansi_16_sgr_param(color: Color, foreground: bool) -> Option<u8> {
+    let idx = ANSI_16_PALETTE
+        .iter()
+        .position(|(palette_color, _)| *palette_color == color)? as u8;
+    let base = if foreground { 30 } else { 40 };
+    Some(if idx < 8 {
+        base + idx
+    } else {
+        base + 60 + idx - 8
+    })
+}
+
+// Parse an X11 color specification `rgb:r/g/b`, where each channel has 1 to 4 hex digits
+// The reply comes from the terminal, so longer channels are rejected rather than trusted
+pub fn parse_x11_rgb(spec: &str) -> Option<(u8, u8, u8)> {
+    let mut channels = spec.strip_prefix("rgb:")?.split('/').map(|channel| {
+        if !(1..=4).contains(&channel.len()) {
+            return None;
+        }
+        let value = u32::from_str_radix(channel, 16).ok()?;
+        let max = 16u32.pow(channel.len() as u32) - 1;
+        Some((value * 255 / max) as u8)
+    });
+    let rgb = (channels.next()??, channels.next()??, channels.next()??);
+    channels.next().is_none().then_some(rgb)
+}
+
+fn distance((r1, g1, b1): (u8, u8, u8), (r2, g2, b2): (u8, u8, u8)) -> u32 {
+    let square = |a: u8, b: u8| (a as i32 - b as i32).pow(2) as u32;
+    square(r1, r2) + square(g1, g2) + square(b1, b2)
+}
+
+fn nearest_16(rgb: (u8, u8, u8)) -> Color {
+    ANSI_16_PALETTE
+        .iter()
+        .min_by_key(|(_, palette_rgb)| distance(rgb, *palette_rgb))
+        .map_or(Color::Reset, |(color, _)| *color)
+}
+
+// Pick the closer of the nearest color cube entry and the nearest grayscale ramp entry
+fn nearest_256((r, g, b): (u8, u8, u8)) -> u8 {
+    let cube_idx = |channel: u8| {
+        (0..CUBE_LEVELS.len())
+            .min_by_key(|idx| (CUBE_LEVELS[*idx] as i32 - channel as i32).abs())
+            .unwrap_or(0) as u8
+    };
+    let (ri, gi, bi) = (cube_idx(r), cube_idx(g), cube_idx(b));
+    let cube_value = 16 + 36 * ri + 6 * gi + bi;
+
+    let average = (r as u32 + g as u32 + b as u32) / 3;
+    let gray_idx = (average.saturating_sub(3) / 10).min(23) as u8;
+    let gray_value = 232 + gray_idx;
+
+    if distance((r, g, b), ansi_256_to_rgb(gray_value))
+        < distance((r, g, b), ansi_256_to_rgb(cube_value))
+    {
+        gray_value
+    } else {
+        cube_value
+    }
+}
+
+fn ansi_256_to_rgb(value: u8) -> (u8, u8, u8) {
+    match value {
+        0..16 => ANSI_16_PALETTE[value as usize].1,
+        16..232 => {
+            let idx = value - 16;
+            (
+                CUBE_LEVELS[(idx / 36) as usize],
+                CUBE_LEVELS[(idx / 6 % 6) as usize],
+                CUBE_LEVELS[(idx % 6) as usize],
+            )
+        }
+        _ => {
+            let level = 8 + 10 * (value - 232);
+            (level, level, level)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quantize_picks_the_nearest_color_of_the_depth() {
+        let red = Color::Rgb {
+            r: 250,
+            g: 10,
+            b: 10,
+        };
+        assert_eq!(ColorDepth::TrueColor.quantize(red), red);
+        assert_eq!(ColorDepth::Ansi256.quantize(red), Color::AnsiValue(196));
+        assert_eq!(ColorDepth::Ansi16.quantize(red), Color::Red);
+        // Grays are closer to the grayscale ramp than to the color cube
+        let gray = Color::Rgb {
+            r: 128,
+            g: 128,
+            b: 128,
+        };
+        assert_eq!(ColorDepth::Ansi256.quantize(gray), Color::AnsiValue(244));
+        assert_eq!(
+            ColorDepth::Ansi16.quantize(Color::AnsiValue(196)),
+            Color::Red
+        );
+        assert_eq!(
+            ColorDepth::Ansi16.quantize(Color::AnsiValue(3)),
+            Color::DarkYellow
+        );
+        assert_eq!(ColorDepth::Ansi16.quantize(Color::Reset), Color::Reset);
+    }
+
+    #[test]
+    fn parse_x11_rgb_scales_each_channel() {
+        assert_eq!(parse_x11_rgb("rgb:ffff/0000/8080"), Some((255, 0, 128)));
+        assert_eq!(parse_x11_rgb("rgb:f/0/8"), Some((255, 0, 136)));
+        assert_eq!(parse_x11_rgb("rgb:ff/00"), None);
+        assert_eq!(parse_x11_rgb("rgb:ff/00/00/00"), None);
+        assert_eq!(parse_x11_rgb("rgb:/00/00"), None);
+        assert_eq!(parse_x11_rgb("rgb:gg/00/00"), None);
+        assert_eq!(parse_x11_rgb("#ff0000"), None);
+        // Channels longer than four digits would overflow the scaling
+        assert_eq!(parse_x11_rgb("rgb:ffffffff/0/0"), None);
+        assert_eq!(parse_x11_rgb("rgb:fffff/0/0"), None);
+    }
+}
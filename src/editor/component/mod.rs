@@ -1 +1,3 @@
+pub mod statusline;
+pub mod tabline;
 pub mod textarea;
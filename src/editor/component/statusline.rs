@@ -0,0 +1,246 @@
+use crate::editor::{
+    drawing_surface::DrawingSurface, theme::HighlightGroup, utility::TerminalPosition,
+    width::DisplayWidth,
+};
+use std::path::Path;
+
+// Mode, file name and flags on the left; pending keys, file type, encoding, line endings
+// and cursor position on the right
+pub const DEFAULT_FORMAT: &str = "%#StatusLineMode# %M %* %f%m%r%=%k  %y %e[%o]  %l,%c-%v  %p%% ";
+
+// What the status line can show, gathered from the editor every time it's drawn
+pub struct StatusInfo<'a> {
+    pub mode: &'a str,
+    pub buffer_number: usize,
+    pub file_name: Option<&'a str>,
+    pub modified: bool,
+    pub read_only: bool,
+    pub file_type: Option<&'a str>,
+    pub line_ending: &'a str,
+    // The cursor position: line and grapheme offset, and display column, all counted from 1
+    pub line: usize,
+    pub offset: usize,
+    pub col: usize,
+    pub line_count: usize,
+    pub pending_keys: &'a str,
+}
+
+#[derive(Clone)]
+enum Item {
+    Text(String),
+    Field(char),
+    Group(HighlightGroup),
+    Align,
+}
+
+// A status line described by a format string, where these items are replaced:
+// `%M` mode, `%n` buffer number, `%f` file name, `%m` `[+]` if modified, `%r` `[RO]` if read-only,
+// `%y` `[filetype]`, `%e` encoding, `%o` line endings (unix/dos),
+// `%l` line, `%L` line count, `%c` grapheme offset, `%v` display column, `%p` percentage
+// through the file, `%k` pending keys, `%%` a literal `%`
+// `%=` separates the left part from the part aligned to the right edge,
+// `%#Group#` draws what follows with a highlight group and `%*` goes back to the base group,
+// StatusLine for the active window and StatusLineNC for the others
+#[derive(Clone)]
+pub struct StatusLine {
+    items: Vec<Item>,
+}
+
+impl Default for StatusLine {
+    fn default() -> StatusLine {
+        StatusLine::parse(DEFAULT_FORMAT).expect("the default format is valid")
+    }
+}
+
+impl StatusLine {
+    pub fn parse(format: &str) -> Result<StatusLine, String> {
+        let mut items = vec![];
+        let mut text = String::new();
+        let mut chars = format.chars();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                text.push(c);
+                continue;
+            }
+            let item = match chars.next() {
+                Some('%') => {
+                    text.push('%');
+                    continue;
+                }
+                Some(
+                    field @ ('M' | 'n' | 'f' | 'm' | 'r' | 'y' | 'e' | 'o' | 'l' | 'L' | 'c' | 'v'
+                    | 'p' | 'k'),
+                ) => Item::Field(field),
+                Some('=') => Item::Align,
+                Some('*') => Item::Group(HighlightGroup::StatusLine),
+                Some('#') => {
+                    let name: String = chars.by_ref().take_while(|c| *c != '#').collect();
+                    Item::Group(
+                        HighlightGroup::from_name(&name)
+                            .ok_or(format!("Unknown highlight group in statusline: {name}"))?,
+                    )
+                }
+                Some(item) => return Err(format!("Unknown statusline item: %{item}")),
+                None => return Err("Statusline format ends with %".into()),
+            };
+            if !text.is_empty() {
+                items.push(Item::Text(std::mem::take(&mut text)));
+            }
+            items.push(item);
+        }
+        if !text.is_empty() {
+            items.push(Item::Text(text));
+        }
+        Ok(StatusLine { items })
+    }
+
+    pub fn draw(
+        &self,
+        surface: &mut dyn DrawingSurface,
+        info: &StatusInfo,
+        base_group: HighlightGroup,
+    ) {
+        let width = surface.get_bounding_rect_size().width;
+        let mut sides: [Vec<(String, HighlightGroup)>; 2] = [vec![], vec![]];
+        let mut side = 0;
+        let mut group = base_group;
+        for item in &self.items {
+            match item {
+                Item::Text(text) => sides[side].push((text.clone(), group)),
+                Item::Field(field) => sides[side].push((Self::expand(*field, info), group)),
+                Item::Group(HighlightGroup::StatusLine) => group = base_group,
+                Item::Group(item_group) => group = *item_group,
+                Item::Align => side = 1,
+            }
+        }
+        let get_width = |segments: &[(String, HighlightGroup)]| -> usize {
+            segments.iter().map(|(text, _)| text.display_width()).sum()
+        };
+        let left_width = get_width(&sides[0]);
+        // The right part is pushed against the right edge, but never over the left part
+        let right_start = (width as usize)
+            .saturating_sub(get_width(&sides[1]))
+            .max(left_width);
+
+        surface.add_highlight(
+            base_group,
+            None,
+            TerminalPosition { col: 0, row: 0 },
+            TerminalPosition { col: width, row: 0 },
+        );
+        for (start_col, segments) in [(0, &sides[0]), (right_start, &sides[1])] {
+            let mut col = start_col;
+            for (text, group) in segments {
+                let text_width = text.display_width();
+                if col >= width as usize {
+                    break;
+                }
+                // A segment's group is drawn over the base group whatever their priorities
+                if *group != base_group {
+                    surface.add_highlight(
+                        *group,
+                        Some(base_group.get_priority() + 1),
+                        TerminalPosition {
+                            col: col as u16,
+                            row: 0,
+                        },
+                        TerminalPosition {
+                            col: (col + text_width).min(width as usize) as u16,
+                            row: 0,
+                        },
+                    );
+                }
+                surface.add_content(
+                    text,
+                    TerminalPosition {
+                        col: col as u16,
+                        row: 0,
+                    },
+                );
+                col += text_width;
+            }
+        }
+    }
+
+    fn expand(field: char, info: &StatusInfo) -> String {
+        let flag = |set: bool, flag: &str| if set { flag.into() } else { String::new() };
+        match field {
+            'M' => info.mode.into(),
+            'n' => info.buffer_number.to_string(),
+            'f' => info.file_name.unwrap_or("[No Name]").into(),
+            'm' => flag(info.modified, "[+]"),
+            'r' => flag(info.read_only, "[RO]"),
+            'y' => info
+                .file_type
+                .map(|file_type| format!("[{file_type}]"))
+                .unwrap_or_default(),
+            'e' => "utf-8".into(),
+            'o' => info.line_ending.into(),
+            'l' => info.line.to_string(),
+            'L' => info.line_count.to_string(),
+            'c' => info.offset.to_string(),
+            'v' => info.col.to_string(),
+            'p' => (info.line * 100 / info.line_count.max(1)).to_string(),
+            'k' => info.pending_keys.into(),
+            _ => String::new(),
+        }
+    }
+}
+
+// Guess the file type from the file name
+pub fn detect_file_type(path: &str) -> Option<&'static str> {
+    let path = Path::new(path);
+    if path.file_name().is_some_and(|name| name == "Makefile") {
+        return Some("make");
+    }
+    let file_type = match path.extension()?.to_str()? {
+        "rs" => "rust",
+        "c" | "h" => "c",
+        "cc" | "cpp" | "cxx" | "hpp" => "cpp",
+        "go" => "go",
+        "py" => "python",
+        "js" | "mjs" => "javascript",
+        "ts" => "typescript",
+        "java" => "java",
+        "rb" => "ruby",
+        "sh" | "bash" => "sh",
+        "html" | "htm" => "html",
+        "css" => "css",
+        "json" => "json",
+        "toml" => "toml",
+        "yaml" | "yml" => "yaml",
+        "md" => "markdown",
+        "txt" => "text",
+        _ => return None,
+    };
+    Some(file_type)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_splits_text_fields_and_groups() {
+        let status_line = StatusLine::parse("%#MoreMsg#%f%* 100%%%=%l").unwrap();
+        assert!(matches!(
+            status_line.items.as_slice(),
+            [
+                Item::Group(HighlightGroup::MoreMsg),
+                Item::Field('f'),
+                Item::Group(HighlightGroup::StatusLine),
+                Item::Text(text),
+                Item::Align,
+                Item::Field('l'),
+            ] if text == " 100%"
+        ));
+    }
+
+    #[test]
+    fn parse_rejects_unknown_items() {
+        assert!(StatusLine::parse("%x").is_err());
+        assert!(StatusLine::parse("%#Nope#").is_err());
+        assert!(StatusLine::parse("50%").is_err());
+        assert!(StatusLine::parse(DEFAULT_FORMAT).is_ok());
+    }
+}
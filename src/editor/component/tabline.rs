@@ -0,0 +1,174 @@
+use crate::editor::{
+    drawing_surface::DrawingSurface, theme::HighlightGroup, utility::TerminalPosition,
+    width::DisplayWidth,
+};
+use unicode_segmentation::UnicodeSegmentation;
+
+// Names are never shortened below this many columns
+const MIN_NAME_WIDTH: usize = 4;
+
+// What a tab page's label shows: the name of the buffer in its active window
+pub struct TabLabel {
+    pub name: String,
+    pub modified: bool,
+    pub window_count: usize,
+}
+
+impl TabLabel {
+    // ` 2 main.rs+ `, the number being the number of windows when there are several
+    fn render(&self, name: &str) -> String {
+        let window_count = if self.window_count > 1 {
+            format!("{} ", self.window_count)
+        } else {
+            String::new()
+        };
+        let modified = if self.modified { "+" } else { "" };
+        format!(" {window_count}{name}{modified} ")
+    }
+}
+
+// Keep the end of the name, which is the most telling part of a path, in at most `width` columns
+fn shorten_name(name: &str, width: usize) -> String {
+    if name.display_width() <= width {
+        return name.into();
+    }
+    let mut kept = vec![];
+    let mut kept_width = 1;
+    for grapheme in name.graphemes(true).rev() {
+        let grapheme_width = grapheme.display_width();
+        if kept_width + grapheme_width > width {
+            break;
+        }
+        kept_width += grapheme_width;
+        kept.push(grapheme);
+    }
+    kept.reverse();
+    format!("<{}", kept.concat())
+}
+
+// The line at the top of the screen with a label per tab page
+// When the labels don't fit, the names are shortened, and if that's not enough, the labels
+// around the current one are shown with `<` and `>` marking the hidden ones
+pub fn draw(surface: &mut dyn DrawingSurface, labels: &[TabLabel], current: usize) {
+    let width = surface.get_bounding_rect_size().width as usize;
+    let fits =
+        |texts: &[String]| texts.iter().map(|text| text.display_width()).sum::<usize>() <= width;
+
+    let mut texts: Vec<String> = labels
+        .iter()
+        .map(|label| label.render(&label.name))
+        .collect();
+    if !fits(&texts) {
+        let overhead = texts
+            .iter()
+            .zip(labels)
+            .map(|(text, label)| text.display_width() - label.name.display_width())
+            .max()
+            .unwrap_or(0);
+        let name_width = (width / labels.len().max(1))
+            .saturating_sub(overhead)
+            .max(MIN_NAME_WIDTH);
+        texts = labels
+            .iter()
+            .map(|label| label.render(&shorten_name(&label.name, name_width)))
+            .collect();
+    }
+
+    // Widen the range of labels shown around the current one while they fit, leaving a
+    // column on each side that has hidden labels for the marker
+    let (mut first, mut last) = (current, current);
+    let get_range_width = |first: usize, last: usize| -> usize {
+        let markers = (first > 0) as usize + (last + 1 < texts.len()) as usize;
+        texts[first..=last]
+            .iter()
+            .map(|text| text.display_width())
+            .sum::<usize>()
+            + markers
+    };
+    loop {
+        if last + 1 < texts.len() && get_range_width(first, last + 1) <= width {
+            last += 1;
+        } else if first > 0 && get_range_width(first - 1, last) <= width {
+            first -= 1;
+        } else {
+            break;
+        }
+    }
+
+    let mut col = 0;
+    if first > 0 {
+        draw_fill(surface, "<", 0, 1);
+        col += 1;
+    }
+    for (idx, text) in texts.iter().enumerate().take(last + 1).skip(first) {
+        let group = if idx == current {
+            HighlightGroup::TabLineSel
+        } else {
+            HighlightGroup::TabLine
+        };
+        let text_width = text.display_width();
+        surface.add_highlight(
+            group,
+            None,
+            TerminalPosition {
+                col: col as u16,
+                row: 0,
+            },
+            TerminalPosition {
+                col: (col + text_width).min(width) as u16,
+                row: 0,
+            },
+        );
+        surface.add_content(
+            text,
+            TerminalPosition {
+                col: col as u16,
+                row: 0,
+            },
+        );
+        col += text_width;
+    }
+    if col < width {
+        draw_fill(surface, "", col, width);
+    }
+    if last + 1 < texts.len() {
+        draw_fill(surface, ">", width.saturating_sub(1), width);
+    }
+}
+
+// The parts of the tab line without labels
+fn draw_fill(surface: &mut dyn DrawingSurface, content: &str, start: usize, end: usize) {
+    surface.add_highlight(
+        HighlightGroup::TabLineFill,
+        None,
+        TerminalPosition {
+            col: start as u16,
+            row: 0,
+        },
+        TerminalPosition {
+            col: end as u16,
+            row: 0,
+        },
+    );
+    surface.add_content(
+        content,
+        TerminalPosition {
+            col: start as u16,
+            row: 0,
+        },
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shorten_name_keeps_the_end_of_the_name() {
+        assert_eq!(shorten_name("src/main.rs", 11), "src/main.rs");
+        assert_eq!(shorten_name("src/main.rs", 8), "<main.rs");
+        assert_eq!(shorten_name("src/main.rs", 1), "<");
+        // A double-width grapheme that doesn't fit is left out whole
+        assert_eq!(shorten_name("a\u{4e00}b", 3), "<b");
+    }
+}
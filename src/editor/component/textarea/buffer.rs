@@ -1,8 +1,27 @@
-use crate::editor::utility::{Direction, GraphemeLocation};
+use crate::editor::{
+    theme::HighlightGroup,
+    utility::{Direction, GraphemeLocation},
+};
 use ropey::Rope;
-use std::cmp::min;
+use std::{
+    cmp::min,
+    io::{Error, Write},
+};
 use unicode_segmentation::UnicodeSegmentation;
 
+// A marker shown in the sign column beside a buffer line, e.g. a mark, a lint result or
+// a change marker, which stays with the line as lines are inserted or deleted above it
+#[derive(Clone)]
+pub struct Sign {
+    // Identifies the sign to whatever placed it, placing a sign replaces the one of the same name
+    pub name: String,
+    // One or two columns wide
+    pub glyph: String,
+    pub group: HighlightGroup,
+    // Only the highest priority sign of a line is shown
+    pub priority: u8,
+}
+
 pub struct Buffer {
     text: Rope,
     // The "raw" current grapheme location
@@ -10,6 +29,12 @@ pub struct Buffer {
     // with one catch: The "raw" offset can surpass the length of a line
     // so the "real" current grapheme location is the "raw" line and "clamped-to-the-line" offset
     raw_current_grapheme_location: GraphemeLocation,
+    // Whether the text has been edited since the buffer was created or last saved
+    modified: bool,
+    // Incremented on every edit, so that observers can tell whether the text has changed
+    version: usize,
+    // The signs and the lines they are placed on
+    signs: Vec<(usize, Sign)>,
 }
 
 impl Buffer {
@@ -17,6 +42,39 @@ impl Buffer {
         Buffer {
             raw_current_grapheme_location: GraphemeLocation::default(),
             text: Rope::from_str(content),
+            modified: false,
+            version: 0,
+            signs: vec![],
+        }
+    }
+
+    pub fn is_modified(&self) -> bool {
+        self.modified
+    }
+
+    pub fn set_modified(&mut self, modified: bool) {
+        self.modified = modified;
+    }
+
+    pub fn get_version(&self) -> usize {
+        self.version
+    }
+
+    pub fn write_to(&self, writer: impl Write) -> Result<(), Error> {
+        self.text.write_to(writer)
+    }
+
+    pub fn get_byte_count(&self) -> usize {
+        self.text.len_bytes()
+    }
+
+    // The line ending format, going by the first line
+    pub fn get_line_ending(&self) -> &'static str {
+        let first_line = self.get_raw_line(0).unwrap_or_default();
+        if first_line.ends_with("\r\n") {
+            "dos"
+        } else {
+            "unix"
         }
     }
 
@@ -38,10 +96,21 @@ impl Buffer {
             .sum::<usize>()
             + self.text.line_to_char(grapheme_loc.line);
         self.text.insert_char(char_idx, c);
+        self.mark_edited();
         self.move_grapheme(Direction::Right);
         self.clamp_grapheme_offset();
     }
 
+    // Overwrite the grapheme under the cursor, or append if the cursor is at the end of the line
+    pub fn type_replace_char(&mut self, c: char) {
+        let grapheme_loc = self.get_grapheme_location();
+        let cur_line_length = self.get_line_length(grapheme_loc.line).unwrap_or(0);
+        if grapheme_loc.offset < cur_line_length {
+            self.type_delete();
+        }
+        self.type_char(c);
+    }
+
     pub fn type_enter(&mut self) {
         let grapheme_loc = self.get_grapheme_location();
         let cur_line = self.get_line(grapheme_loc.line);
@@ -56,6 +125,13 @@ impl Buffer {
             .sum::<usize>()
             + self.text.line_to_char(grapheme_loc.line);
         self.text.insert_char(char_idx, '\n');
+        self.mark_edited();
+        // Breaking a line at its start pushes all of it down, signs included
+        if grapheme_loc.offset == 0 {
+            self.move_signs_from(grapheme_loc.line, 1);
+        } else {
+            self.move_signs_from(grapheme_loc.line + 1, 1);
+        }
         self.move_grapheme_to_start_of_line(grapheme_loc.line + 1);
     }
 
@@ -69,9 +145,12 @@ impl Buffer {
             let prev_line_char_idx = self.text.line_to_char(grapheme_loc.line - 1);
             let prev_line = self.get_raw_line(grapheme_loc.line - 1).unwrap();
             let start_char_idx =
-                prev_line_char_idx + prev_line.trim_matches(&['\r', '\n']).chars().count();
+                prev_line_char_idx + prev_line.trim_matches(['\r', '\n']).chars().count();
             let end_char_idx = prev_line_char_idx + prev_line.chars().count();
             self.text.remove(start_char_idx..end_char_idx);
+            self.mark_edited();
+            // The line is joined to the previous one, and so are its signs
+            self.move_signs_from(grapheme_loc.line, -1);
         } else {
             self.move_grapheme(Direction::Left);
             let cur_line_char_idx = self.text.line_to_char(grapheme_loc.line);
@@ -89,6 +168,7 @@ impl Buffer {
                 .sum::<usize>()
                 + cur_line_char_idx;
             self.text.remove(start_char_idx..end_char_idx);
+            self.mark_edited();
         }
     }
 
@@ -103,6 +183,27 @@ impl Buffer {
         self.type_backspace();
     }
 
+    // Replace the leading whitespace of a line, keeping the cursor on the same character
+    pub fn replace_indent(&mut self, line_idx: usize, indent: &str) {
+        let Some(line) = self.get_line(line_idx) else {
+            return;
+        };
+        let old_indent_length = line.chars().take_while(|c| *c == ' ' || *c == '\t').count();
+        let new_indent_length = indent.chars().count();
+        let grapheme_loc = self.get_grapheme_location();
+
+        let line_char_idx = self.text.line_to_char(line_idx);
+        self.text
+            .remove(line_char_idx..line_char_idx + old_indent_length);
+        self.text.insert(line_char_idx, indent);
+        self.mark_edited();
+
+        if grapheme_loc.line == line_idx {
+            self.raw_current_grapheme_location.offset =
+                grapheme_loc.offset.saturating_sub(old_indent_length) + new_indent_length;
+        }
+    }
+
     pub fn move_grapheme(&mut self, direction: Direction) {
         match direction {
             Direction::Up => {
@@ -147,6 +248,60 @@ impl Buffer {
         self.raw_current_grapheme_location.line = line_idx;
     }
 
+    pub fn move_grapheme_to(&mut self, location: GraphemeLocation) {
+        let line_count = self.get_line_count();
+        self.raw_current_grapheme_location = GraphemeLocation {
+            line: min(location.line, line_count.saturating_sub(1)),
+            offset: location.offset,
+        };
+        self.clamp_grapheme_offset();
+    }
+
+    pub fn place_sign(&mut self, line_idx: usize, sign: Sign) {
+        self.remove_sign(&sign.name);
+        self.signs.push((line_idx, sign));
+    }
+
+    pub fn remove_sign(&mut self, name: &str) {
+        self.signs.retain(|(_, sign)| sign.name != name);
+    }
+
+    pub fn get_sign_line(&self, name: &str) -> Option<usize> {
+        self.signs
+            .iter()
+            .find(|(_, sign)| sign.name == name)
+            .map(|(line_idx, _)| *line_idx)
+    }
+
+    pub fn has_signs(&self) -> bool {
+        !self.signs.is_empty()
+    }
+
+    // The sign shown for a line, the most recently placed one among those of the highest priority
+    pub fn get_line_sign(&self, line_idx: usize) -> Option<&Sign> {
+        self.signs
+            .iter()
+            .filter(|(sign_line_idx, _)| *sign_line_idx == line_idx)
+            .map(|(_, sign)| sign)
+            .max_by_key(|sign| sign.priority)
+    }
+
+    // Move the signs of the lines from `line_idx` on by `delta` lines, as lines are inserted
+    // before it or removed, the signs of removed lines end up on the line before them
+    fn move_signs_from(&mut self, line_idx: usize, delta: isize) {
+        let min_line_idx = line_idx.saturating_add_signed(delta.min(0));
+        for (sign_line_idx, _) in &mut self.signs {
+            if *sign_line_idx >= line_idx {
+                *sign_line_idx = sign_line_idx.saturating_add_signed(delta).max(min_line_idx);
+            }
+        }
+    }
+
+    fn mark_edited(&mut self) {
+        self.modified = true;
+        self.version += 1;
+    }
+
     fn clamp_grapheme_offset(&mut self) {
         self.raw_current_grapheme_location.offset = self.get_clamped_grapheme_offset();
     }
@@ -167,7 +322,7 @@ impl Buffer {
 
     pub fn get_line(&self, line: usize) -> Option<String> {
         let line: String = self.text.get_line(line)?.chars().collect();
-        Some(line.trim_end_matches(&['\r', '\n']).into())
+        Some(line.trim_end_matches(['\r', '\n']).into())
     }
 
     fn get_raw_line(&self, line: usize) -> Option<String> {
@@ -183,3 +338,46 @@ impl Buffer {
         Some(line.graphemes(true).count())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn place_mark(buffer: &mut Buffer, line_idx: usize) {
+        buffer.place_sign(
+            line_idx,
+            Sign {
+                name: "mark:a".into(),
+                glyph: "a".into(),
+                group: HighlightGroup::MarkSign,
+                priority: 0,
+            },
+        );
+    }
+
+    #[test]
+    fn signs_follow_lines_on_enter() {
+        let mut buffer = Buffer::new("one\ntwo\nthree");
+        place_mark(&mut buffer, 1);
+        buffer.move_grapheme_to(GraphemeLocation { line: 1, offset: 0 });
+        buffer.type_enter();
+        assert_eq!(buffer.get_sign_line("mark:a"), Some(2));
+
+        // Breaking the line after its start leaves the sign where it is
+        buffer.move_grapheme_to(GraphemeLocation { line: 2, offset: 1 });
+        buffer.type_enter();
+        assert_eq!(buffer.get_sign_line("mark:a"), Some(2));
+    }
+
+    #[test]
+    fn signs_follow_lines_on_join() {
+        let mut buffer = Buffer::new("one\ntwo\nthree");
+        place_mark(&mut buffer, 2);
+        buffer.move_grapheme_to(GraphemeLocation { line: 1, offset: 0 });
+        buffer.type_backspace();
+        assert_eq!(buffer.get_sign_line("mark:a"), Some(1));
+        buffer.move_grapheme_to(GraphemeLocation { line: 1, offset: 0 });
+        buffer.type_backspace();
+        assert_eq!(buffer.get_sign_line("mark:a"), Some(0));
+    }
+}
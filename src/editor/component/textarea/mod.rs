@@ -1,27 +1,102 @@
 use crate::editor::{
-    drawing_surface::DrawingSurface,
+    drawing_surface::{
+        DrawingSurface,
+        layout::{Constraint, Layout},
+    },
+    options::{LineNumbers, ListChars, Options, SignColumn, TabOptions, WrapOptions},
+    theme::HighlightGroup,
     utility::{Direction, GraphemeLocation, RenderPosition, TerminalPosition, TerminalSize},
+    width::DisplayWidth,
 };
-use buffer::Buffer;
-use crossterm::event::{KeyCode, KeyEvent};
+use buffer::{Buffer, Sign};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::{borrow::Cow, collections::HashSet};
 use unicode_segmentation::UnicodeSegmentation;
-use unicode_width::UnicodeWidthStr;
 
 pub mod buffer;
 
 pub struct Textarea {
     origin: RenderPosition,
+    // With wrapping, the rows of the top line scrolled off above the textarea, which only
+    // happens to a line with more rows than the textarea
+    skipped_rows: usize,
     buffer: Buffer,
+    tab_options: TabOptions,
+    // The glyphs invisible characters are drawn with, `None` when list mode is off
+    list_chars: Option<ListChars>,
+    // How long lines are wrapped, `None` to scroll horizontally instead
+    wrap: Option<WrapOptions>,
+    line_numbers: LineNumbers,
+    sign_column: SignColumn,
+    // The width the textarea was last drawn with, which wrapped rows depend on
+    width: usize,
+}
+
+// Where the cursor is and what part of the text is shown, which windows showing the same buffer
+// each keep their own of
+#[derive(Clone, Copy)]
+pub struct TextareaView {
+    cursor: GraphemeLocation,
+    origin: RenderPosition,
+    skipped_rows: usize,
+    width: usize,
+}
+
+// A line as it's drawn, with the columns drawn in a highlight of their own,
+// e.g. the glyphs of list mode
+struct RenderableLine {
+    content: String,
+    highlights: Vec<(HighlightGroup, usize, usize)>,
+}
+
+// The columns of a rendered line shown on one row of the textarea,
+// a continuation being any row but the first of a wrapped line
+struct ScreenRow {
+    start_col: usize,
+    end_col: usize,
+    continuation: bool,
 }
 
 impl Textarea {
     pub fn new(content: &str) -> Textarea {
         Textarea {
             origin: RenderPosition::default(),
+            skipped_rows: 0,
             buffer: Buffer::new(content),
+            tab_options: TabOptions::default(),
+            list_chars: None,
+            wrap: None,
+            line_numbers: LineNumbers::Off,
+            sign_column: SignColumn::Auto,
+            width: 0,
+        }
+    }
+
+    pub fn apply_options(&mut self, options: &Options) {
+        self.tab_options = options.tabs;
+        self.list_chars = options.get_list_chars();
+        self.wrap = options.get_wrap_options();
+        self.line_numbers = options.get_line_numbers();
+        self.sign_column = options.sign_column;
+    }
+
+    pub fn get_view(&self) -> TextareaView {
+        TextareaView {
+            cursor: self.buffer.get_grapheme_location(),
+            origin: self.origin,
+            skipped_rows: self.skipped_rows,
+            width: self.width,
         }
     }
 
+    // The cursor is moved back into the text if it has shrunk since the view was taken
+    pub fn set_view(&mut self, view: TextareaView) {
+        self.buffer.move_grapheme_to(view.cursor);
+        self.origin = view.origin;
+        self.skipped_rows = view.skipped_rows;
+        self.width = view.width;
+    }
+
     pub fn set_content(&mut self, content: &str) {
         self.buffer = Buffer::new(content);
     }
@@ -30,38 +105,310 @@ impl Textarea {
         &self.buffer
     }
 
+    pub fn get_content_mut(&mut self) -> &mut Buffer {
+        &mut self.buffer
+    }
+
     pub fn draw(&mut self, surface: &mut dyn DrawingSurface) {
+        let gutter_width = self.get_gutter_width();
+        if gutter_width == 0 {
+            self.draw_text(surface, None);
+            return;
+        }
+        let [mut gutter, mut text_surface] = Self::get_layout(gutter_width).split(surface);
+        self.draw_text(text_surface.as_mut(), Some(gutter.as_mut()));
+    }
+
+    pub fn focus(&mut self, surface: &mut dyn DrawingSurface) {
+        let gutter_width = self.get_gutter_width();
+        if gutter_width == 0 {
+            self.focus_text(surface);
+            return;
+        }
+        let [_, mut text_surface] = Self::get_layout(gutter_width).split(surface);
+        self.focus_text(text_surface.as_mut());
+    }
+
+    // Draw the lines, and their signs and numbers in the gutter if there is one
+    fn draw_text(
+        &mut self,
+        surface: &mut dyn DrawingSurface,
+        mut gutter: Option<&mut dyn DrawingSurface>,
+    ) {
         let size = surface.get_bounding_rect_size();
         self.scroll_cursor_into_view(size);
         let line_count = self.buffer.get_line_count();
-        for line_idx in 0..size.height {
-            let buffer_line_idx = line_idx as usize + self.origin.row;
-            let content: String = if buffer_line_idx >= line_count {
-                "~".into()
+        let mut buffer_line_idx = self.origin.row;
+        let mut surface_row = 0;
+        while surface_row < size.height {
+            let Some(line) = self
+                .get_renderable_line(buffer_line_idx)
+                .filter(|_| buffer_line_idx < line_count)
+            else {
+                surface.add_highlight(
+                    HighlightGroup::NonText,
+                    None,
+                    TerminalPosition {
+                        col: 0,
+                        row: surface_row,
+                    },
+                    TerminalPosition {
+                        col: 1,
+                        row: surface_row,
+                    },
+                );
+                surface.add_content(
+                    "~",
+                    TerminalPosition {
+                        col: 0,
+                        row: surface_row,
+                    },
+                );
+                surface_row += 1;
+                continue;
+            };
+            let skipped_rows = if buffer_line_idx == self.origin.row {
+                self.skipped_rows
             } else {
-                self.get_renderable_line(buffer_line_idx)
-                    .unwrap_or("".into())
-                    .graphemes(true)
-                    .skip(self.origin.col)
-                    .collect()
+                0
             };
-            surface.add_content(
-                &content,
+            for row in self.get_screen_rows(&line).into_iter().skip(skipped_rows) {
+                if surface_row >= size.height {
+                    break;
+                }
+                if let Some(gutter) = gutter.as_deref_mut() {
+                    self.draw_gutter_row(gutter, surface_row, buffer_line_idx, row.continuation);
+                }
+                self.draw_screen_row(surface, surface_row, &line, &row);
+                surface_row += 1;
+            }
+            buffer_line_idx += 1;
+        }
+    }
+
+    fn focus_text(&mut self, surface: &mut dyn DrawingSurface) {
+        let size = surface.get_bounding_rect_size();
+        self.scroll_cursor_into_view(size);
+        let RenderPosition { col, row } = self.get_surface_position_of_cursor();
+        surface.add_cursor(TerminalPosition {
+            col: col.min(size.width.saturating_sub(1) as usize) as u16,
+            row: row as u16,
+        });
+    }
+
+    // The gutter to the left of the text, sharing a window too narrow for both with the text
+    // instead of taking all of it
+    fn get_layout(gutter_width: u16) -> Layout<2> {
+        Layout::columns([Constraint::Max(gutter_width), Constraint::Fill])
+    }
+
+    fn get_gutter_width(&self) -> u16 {
+        self.get_sign_column_width() + self.get_number_width()
+    }
+
+    fn get_sign_column_width(&self) -> u16 {
+        let shown = match self.sign_column {
+            SignColumn::Auto => self.buffer.has_signs(),
+            SignColumn::Yes => true,
+            SignColumn::No => false,
+        };
+        if shown { 2 } else { 0 }
+    }
+
+    // Wide enough for the number of the last line and a space, but never narrower than
+    // 3 digits so that it doesn't change width all the time in short buffers
+    fn get_number_width(&self) -> u16 {
+        if self.line_numbers == LineNumbers::Off {
+            return 0;
+        }
+        let digit_count = self.buffer.get_line_count().to_string().len();
+        digit_count.max(3) as u16 + 1
+    }
+
+    // Rows a line is continued on get neither a sign nor a number
+    fn draw_gutter_row(
+        &self,
+        gutter: &mut dyn DrawingSurface,
+        surface_row: u16,
+        buffer_line_idx: usize,
+        continuation: bool,
+    ) {
+        let sign_column_width = self.get_sign_column_width();
+        if sign_column_width > 0 {
+            gutter.add_highlight(
+                HighlightGroup::SignColumn,
+                None,
                 TerminalPosition {
                     col: 0,
-                    row: line_idx,
+                    row: surface_row,
+                },
+                TerminalPosition {
+                    col: sign_column_width,
+                    row: surface_row,
                 },
             );
+            if let Some(sign) = self
+                .buffer
+                .get_line_sign(buffer_line_idx)
+                .filter(|_| !continuation)
+            {
+                gutter.add_highlight(
+                    sign.group,
+                    None,
+                    TerminalPosition {
+                        col: 0,
+                        row: surface_row,
+                    },
+                    TerminalPosition {
+                        col: sign_column_width,
+                        row: surface_row,
+                    },
+                );
+                gutter.add_content(
+                    &sign.glyph,
+                    TerminalPosition {
+                        col: 0,
+                        row: surface_row,
+                    },
+                );
+            }
+        }
+        if !continuation {
+            self.draw_line_number(gutter, surface_row, buffer_line_idx);
         }
     }
 
-    pub fn focus(&mut self, surface: &mut dyn DrawingSurface) {
-        let size = surface.get_bounding_rect_size();
-        self.scroll_cursor_into_view(size);
-        let RenderPosition { col, row } = self.get_render_position_of_cursor();
-        surface.add_cursor(TerminalPosition {
-            col: (col - self.origin.col) as u16,
-            row: (row - self.origin.row) as u16,
+    fn draw_line_number(
+        &self,
+        gutter: &mut dyn DrawingSurface,
+        surface_row: u16,
+        buffer_line_idx: usize,
+    ) {
+        let cursor_line_idx = self.buffer.get_grapheme_location().line;
+        let is_cursor_line = buffer_line_idx == cursor_line_idx;
+        let number_width = self.get_number_width().saturating_sub(1) as usize;
+        let number_col = self.get_sign_column_width();
+        let relative_number = buffer_line_idx.abs_diff(cursor_line_idx);
+        let number = match self.line_numbers {
+            LineNumbers::Absolute => format!("{:>number_width$} ", buffer_line_idx + 1),
+            LineNumbers::Relative => format!("{relative_number:>number_width$} "),
+            // The cursor line shows its absolute number, left aligned to stand out
+            LineNumbers::Hybrid if is_cursor_line => {
+                format!("{:<number_width$} ", buffer_line_idx + 1)
+            }
+            LineNumbers::Hybrid => format!("{relative_number:>number_width$} "),
+            LineNumbers::Off => return,
+        };
+        let group = if is_cursor_line {
+            HighlightGroup::CursorLineNr
+        } else {
+            HighlightGroup::LineNr
+        };
+        gutter.add_highlight(
+            group,
+            None,
+            TerminalPosition {
+                col: number_col,
+                row: surface_row,
+            },
+            TerminalPosition {
+                col: number_col + number_width as u16,
+                row: surface_row,
+            },
+        );
+        gutter.add_content(
+            &number,
+            TerminalPosition {
+                col: number_col,
+                row: surface_row,
+            },
+        );
+    }
+
+    // The display column of the cursor within its line, regardless of scrolling and wrapping
+    pub fn get_cursor_col(&self) -> usize {
+        self.get_render_position_of_cursor().col
+    }
+
+    // Set the mark `name` to the cursor line, shown in the sign column
+    pub fn set_mark(&mut self, name: char) {
+        let line_idx = self.buffer.get_grapheme_location().line;
+        self.buffer.place_sign(
+            line_idx,
+            Sign {
+                name: format!("mark:{name}"),
+                glyph: name.into(),
+                group: HighlightGroup::MarkSign,
+                priority: 10,
+            },
+        );
+    }
+
+    // Move the cursor to the first non-blank character of the line of the mark `name`
+    pub fn jump_to_mark(&mut self, name: char) -> Result<(), String> {
+        let line_idx = self
+            .buffer
+            .get_sign_line(&format!("mark:{name}"))
+            .ok_or("Mark not set")?;
+        let offset = self
+            .buffer
+            .get_line(line_idx)
+            .unwrap_or_default()
+            .graphemes(true)
+            .take_while(|grapheme| *grapheme == " " || *grapheme == "\t")
+            .count();
+        self.buffer.move_grapheme_to(GraphemeLocation {
+            offset,
+            line: line_idx,
+        });
+        Ok(())
+    }
+
+    // Move the cursor to the row below or above on the screen,
+    // which is only another buffer line when the line isn't wrapped
+    pub fn move_screen_row(&mut self, direction: Direction) {
+        if self.wrap.is_none() {
+            self.buffer.move_grapheme(direction);
+            return;
+        }
+        let RenderPosition { col, row: line_idx } = self.get_render_position_of_cursor();
+        let Some(line) = self.get_renderable_line(line_idx) else {
+            return;
+        };
+        let rows = self.get_screen_rows(&line);
+        let row_idx = Self::find_screen_row(&rows, col);
+        let screen_col = col - rows[row_idx].start_col + self.get_prefix_width(&rows[row_idx]);
+
+        let (target_line_idx, target_rows, target_row_idx) = match direction {
+            Direction::Down if row_idx + 1 < rows.len() => (line_idx, rows, row_idx + 1),
+            Direction::Down if line_idx + 1 < self.buffer.get_line_count() => {
+                let Some(next_line) = self.get_renderable_line(line_idx + 1) else {
+                    return;
+                };
+                (line_idx + 1, self.get_screen_rows(&next_line), 0)
+            }
+            Direction::Up if row_idx > 0 => (line_idx, rows, row_idx - 1),
+            Direction::Up if line_idx > 0 => {
+                let Some(prev_line) = self.get_renderable_line(line_idx - 1) else {
+                    return;
+                };
+                let prev_rows = self.get_screen_rows(&prev_line);
+                let last_row_idx = prev_rows.len() - 1;
+                (line_idx - 1, prev_rows, last_row_idx)
+            }
+            _ => return,
+        };
+        let target_row = &target_rows[target_row_idx];
+        let mut target_col =
+            target_row.start_col + screen_col.saturating_sub(self.get_prefix_width(target_row));
+        // The last column of a row that isn't the last one belongs to the next row
+        if target_row_idx + 1 < target_rows.len() {
+            target_col = target_col.min(target_row.end_col.saturating_sub(1));
+        }
+        let offset = self.get_offset_at_col(target_line_idx, target_col);
+        self.buffer.move_grapheme_to(GraphemeLocation {
+            offset,
+            line: target_line_idx,
         });
     }
 
@@ -70,7 +417,18 @@ impl Textarea {
             return;
         }
 
-        let KeyEvent { code, .. } = event;
+        let KeyEvent {
+            code, modifiers, ..
+        } = event;
+
+        if modifiers.contains(KeyModifiers::CONTROL) {
+            match code {
+                KeyCode::Char('t') => self.shift_indent(true),
+                KeyCode::Char('d') => self.shift_indent(false),
+                _ => {}
+            }
+            return;
+        }
 
         match code {
             KeyCode::Up => self.buffer.move_grapheme(Direction::Up),
@@ -80,16 +438,31 @@ impl Textarea {
             KeyCode::Char(c) => self.buffer.type_char(c),
             KeyCode::Enter => self.buffer.type_enter(),
             KeyCode::Delete => self.buffer.type_delete(),
-            KeyCode::Backspace => self.buffer.type_backspace(),
-            KeyCode::Tab => self.buffer.type_char('\t'),
+            KeyCode::Backspace => self.type_backspace(),
+            KeyCode::Tab => self.type_tab(),
             _ => {}
         }
     }
 
+    // Handle a key the way Replace mode does: printable characters overwrite instead of insert
+    pub fn handle_key_replace(&mut self, event: KeyEvent) {
+        if !event.is_press() {
+            return;
+        }
+
+        match event.code {
+            KeyCode::Char(c) => self.buffer.type_replace_char(c),
+            KeyCode::Tab => self.buffer.type_replace_char('\t'),
+            _ => self.handle_key(event),
+        }
+    }
+
+    #[allow(dead_code)]
     pub fn move_to_end_of_line(&mut self, line_idx: usize) {
         self.buffer.move_grapheme_to_end_of_line(line_idx);
     }
 
+    #[allow(dead_code)]
     pub fn move_to_start_of_line(&mut self, line_idx: usize) {
         self.buffer.move_grapheme_to_start_of_line(line_idx);
     }
@@ -99,55 +472,519 @@ impl Textarea {
             .move_grapheme_to_end_of_line(self.buffer.get_grapheme_location().line);
     }
 
+    #[allow(dead_code)]
     pub fn move_to_start_of_current_line(&mut self) {
         self.buffer
             .move_grapheme_to_start_of_line(self.buffer.get_grapheme_location().line);
     }
 
-    fn get_renderable_line(&self, buffer_line_idx: usize) -> Option<String> {
-        let line = self.buffer.get_line(buffer_line_idx)?;
-        let renderable_line = line
+    // Insert a tab, or spaces (and tabs when not expanded) up to the next softtabstop
+    fn type_tab(&mut self) {
+        let tabs = self.tab_options;
+        if !tabs.expandtab && tabs.softtabstop == 0 {
+            self.buffer.type_char('\t');
+            return;
+        }
+        let mut col = self.get_render_position_of_cursor().col;
+        let softtabstop = tabs.get_softtabstop();
+        let target_col = (col / softtabstop + 1) * softtabstop;
+        while col < target_col {
+            if !tabs.expandtab && col + tabs.get_tab_width(col) <= target_col {
+                self.buffer.type_char('\t');
+                col += tabs.get_tab_width(col);
+            } else {
+                self.buffer.type_char(' ');
+                col += 1;
+            }
+        }
+    }
+
+    // With softtabstop, the spaces back to the previous softtabstop are deleted as if they were
+    // a tab
+    fn type_backspace(&mut self) {
+        let col = self.get_render_position_of_cursor().col;
+        if self.tab_options.softtabstop == 0 || col == 0 {
+            self.buffer.type_backspace();
+            return;
+        }
+        let softtabstop = self.tab_options.get_softtabstop();
+        let target_col = (col - 1) / softtabstop * softtabstop;
+        let GraphemeLocation { offset, line } = self.buffer.get_grapheme_location();
+        let cur_line = self.buffer.get_line(line).unwrap_or_default();
+        let space_count = cur_line
             .graphemes(true)
-            .map(Self::get_renderable_grapheme)
-            .collect::<String>();
+            .take(offset)
+            .collect::<Vec<_>>()
+            .into_iter()
+            .rev()
+            .take_while(|grapheme| *grapheme == " ")
+            .count()
+            .min(col - target_col);
+        for _ in 0..space_count.max(1) {
+            self.buffer.type_backspace();
+        }
+    }
+
+    // Indent or dedent the cursor line to the next multiple of shiftwidth
+    fn shift_indent(&mut self, right: bool) {
+        let tabs = self.tab_options;
+        let line_idx = self.buffer.get_grapheme_location().line;
+        let Some(line) = self.buffer.get_line(line_idx) else {
+            return;
+        };
+        let indent: String = line
+            .chars()
+            .take_while(|c| *c == ' ' || *c == '\t')
+            .collect();
+        let indent_col = self.get_render_col(&indent, usize::MAX);
+        let shiftwidth = tabs.get_shiftwidth();
+        let new_indent_col = if right {
+            (indent_col / shiftwidth + 1) * shiftwidth
+        } else {
+            indent_col.saturating_sub(1) / shiftwidth * shiftwidth
+        };
+        let new_indent = if tabs.expandtab {
+            " ".repeat(new_indent_col)
+        } else {
+            "\t".repeat(new_indent_col / tabs.tabstop) + &" ".repeat(new_indent_col % tabs.tabstop)
+        };
+        self.buffer.replace_indent(line_idx, &new_indent);
+    }
+
+    fn get_renderable_line(&self, buffer_line_idx: usize) -> Option<RenderableLine> {
+        let line = self.buffer.get_line(buffer_line_idx)?;
+        let trail_start = line.trim_end_matches(' ').graphemes(true).count();
+        let mut col = 0;
+        let mut renderable_line = RenderableLine {
+            content: String::new(),
+            highlights: vec![],
+        };
+        for (offset, grapheme) in line.graphemes(true).enumerate() {
+            let (renderable_grapheme, group) =
+                self.get_renderable_grapheme(grapheme, col, offset >= trail_start);
+            let width = renderable_grapheme.display_width();
+            if let Some(group) = group {
+                renderable_line.highlights.push((group, col, col + width));
+            }
+            renderable_line.content.push_str(&renderable_grapheme);
+            col += width;
+        }
+        if let Some(eol) = self.list_chars.and_then(|list_chars| list_chars.eol) {
+            renderable_line.content.push(eol);
+            renderable_line
+                .highlights
+                .push((HighlightGroup::NonText, col, col + 1));
+        }
         Some(renderable_line)
     }
 
-    fn get_renderable_grapheme<'a>(grapheme: &'a str) -> &'a str {
-        if grapheme == " " || grapheme == "\t" {
-            return " ";
+    // The column the grapheme at `offset` of the line is rendered at
+    // Trailing spaces are drawn as wide as other spaces, so they needn't be told apart here
+    fn get_render_col(&self, line: &str, offset: usize) -> usize {
+        line.graphemes(true).take(offset).fold(0, |col, grapheme| {
+            col + self
+                .get_renderable_grapheme(grapheme, col, false)
+                .0
+                .display_width()
+        })
+    }
+
+    // The rows a rendered line takes on screen: one when not wrapping, starting at the
+    // horizontal scroll, otherwise as many as needed to fit the line in the textarea's width
+    fn get_screen_rows(&self, line: &RenderableLine) -> Vec<ScreenRow> {
+        let Some(wrap) = &self.wrap else {
+            return vec![ScreenRow {
+                start_col: self.origin.col,
+                end_col: usize::MAX,
+                continuation: false,
+            }];
+        };
+        let showbreak_width = self.get_showbreak().display_width();
+        let break_points = if wrap.linebreak {
+            Self::find_break_points(&line.content)
+        } else {
+            HashSet::new()
+        };
+
+        let graphemes: Vec<(usize, &str)> = line.content.grapheme_indices(true).collect();
+        let mut rows = vec![];
+        let mut row_start_col = 0;
+        let mut col = 0;
+        // The index of the grapheme after the last break point on the row, and its column
+        let mut last_break = None;
+        let mut idx = 0;
+        while let Some(&(byte_idx, grapheme)) = graphemes.get(idx) {
+            let grapheme_width = grapheme.display_width();
+            if break_points.contains(&byte_idx) && col > row_start_col {
+                last_break = Some((idx, col));
+            }
+            let available_width = if rows.is_empty() {
+                self.width
+            } else {
+                self.width - showbreak_width
+            };
+            if col + grapheme_width - row_start_col > available_width && col > row_start_col {
+                let (break_idx, break_col) = last_break.take().unwrap_or((idx, col));
+                rows.push(ScreenRow {
+                    start_col: row_start_col,
+                    end_col: break_col,
+                    continuation: !rows.is_empty(),
+                });
+                // Lay out what follows the break again, the continuation row being narrower
+                row_start_col = break_col;
+                col = break_col;
+                idx = break_idx;
+                continue;
+            }
+            col += grapheme_width;
+            idx += 1;
+        }
+        rows.push(ScreenRow {
+            start_col: row_start_col,
+            end_col: col,
+            continuation: !rows.is_empty(),
+        });
+        rows
+    }
+
+    // Where a line may be broken at a word boundary: after whitespace and between wide (CJK)
+    // characters, which is what the Unicode line breaking rules mostly come down to in text
+    fn find_break_points(content: &str) -> HashSet<usize> {
+        let is_whitespace = |segment: &str| segment.chars().all(char::is_whitespace);
+        let is_wide = |segment: &str| segment.display_width() > 1;
+        let mut break_points = HashSet::new();
+        let mut prev_segment = None;
+        for (byte_idx, segment) in content.split_word_bound_indices() {
+            if let Some(prev_segment) = prev_segment
+                && ((is_whitespace(prev_segment) && !is_whitespace(segment))
+                    || (is_wide(prev_segment) && is_wide(segment)))
+            {
+                break_points.insert(byte_idx);
+            }
+            prev_segment = Some(segment);
+        }
+        break_points
+    }
+
+    // The row of `rows` showing the column `col` of the line
+    fn find_screen_row(rows: &[ScreenRow], col: usize) -> usize {
+        rows.iter()
+            .rposition(|row| row.start_col <= col)
+            .unwrap_or(0)
+    }
+
+    // The showbreak marker, unless it leaves no room for the text
+    fn get_showbreak(&self) -> &str {
+        match &self.wrap {
+            Some(wrap) if wrap.showbreak.display_width() < self.width => &wrap.showbreak,
+            _ => "",
+        }
+    }
+
+    fn get_prefix_width(&self, row: &ScreenRow) -> usize {
+        if row.continuation {
+            self.get_showbreak().display_width()
+        } else {
+            0
+        }
+    }
+
+    fn draw_screen_row(
+        &self,
+        surface: &mut dyn DrawingSurface,
+        surface_row: u16,
+        line: &RenderableLine,
+        row: &ScreenRow,
+    ) {
+        let prefix_width = self.get_prefix_width(row);
+        let to_surface_col =
+            |col: usize| (col - row.start_col + prefix_width).min(self.width) as u16;
+        if prefix_width > 0 {
+            surface.add_highlight(
+                HighlightGroup::NonText,
+                None,
+                TerminalPosition {
+                    col: 0,
+                    row: surface_row,
+                },
+                TerminalPosition {
+                    col: prefix_width as u16,
+                    row: surface_row,
+                },
+            );
+            surface.add_content(
+                self.get_showbreak(),
+                TerminalPosition {
+                    col: 0,
+                    row: surface_row,
+                },
+            );
+        }
+        for (group, start_col, end_col) in &line.highlights {
+            if *end_col <= row.start_col || *start_col >= row.end_col {
+                continue;
+            }
+            surface.add_highlight(
+                *group,
+                None,
+                TerminalPosition {
+                    col: to_surface_col((*start_col).max(row.start_col)),
+                    row: surface_row,
+                },
+                TerminalPosition {
+                    col: to_surface_col((*end_col).min(row.end_col)),
+                    row: surface_row,
+                },
+            );
+        }
+        surface.add_content(
+            &Self::clip_line(&line.content, row.start_col, row.end_col),
+            TerminalPosition {
+                col: prefix_width as u16,
+                row: surface_row,
+            },
+        );
+    }
+
+    // Keep the columns `start_col..end_col`, a double-width grapheme cut in half by the start
+    // shows as a blank
+    fn clip_line(line: &str, start_col: usize, end_col: usize) -> String {
+        let mut col = 0;
+        let mut clipped = String::new();
+        for grapheme in line.graphemes(true) {
+            let width = grapheme.display_width();
+            if col + width > end_col {
+                break;
+            }
+            if col >= start_col {
+                clipped.push_str(grapheme);
+            } else if col + width > start_col {
+                clipped.push_str(&" ".repeat(col + width - start_col));
+            }
+            col += width;
+        }
+        clipped
+    }
+
+    // The offset of the grapheme of a buffer line that is rendered over the column `col`
+    fn get_offset_at_col(&self, line_idx: usize, col: usize) -> usize {
+        let line = self.buffer.get_line(line_idx).unwrap_or_default();
+        let mut grapheme_col = 0;
+        for (offset, grapheme) in line.graphemes(true).enumerate() {
+            grapheme_col += self
+                .get_renderable_grapheme(grapheme, grapheme_col, false)
+                .0
+                .display_width();
+            if grapheme_col > col {
+                return offset;
+            }
+        }
+        line.graphemes(true).count()
+    }
+
+    // How a grapheme rendered at `col` is drawn, and the highlight to draw it with if any
+    // A tab is expanded to the next tabstop, control characters are shown in caret notation
+    // and list mode replaces invisible characters with glyphs
+    fn get_renderable_grapheme<'a>(
+        &self,
+        grapheme: &'a str,
+        col: usize,
+        trailing: bool,
+    ) -> (Cow<'a, str>, Option<HighlightGroup>) {
+        let list_chars = self.list_chars;
+        let whitespace = |glyph: char| (glyph.to_string().into(), Some(HighlightGroup::Whitespace));
+        if grapheme == "\t" {
+            let width = self.tab_options.get_tab_width(col);
+            return match list_chars.and_then(|list_chars| list_chars.tab) {
+                Some(tab) => (tab.render(width).into(), Some(HighlightGroup::Whitespace)),
+                None => (" ".repeat(width).into(), None),
+            };
         }
-        if grapheme.chars().nth(0).unwrap_or(' ').is_control() {
-            return "▯";
+        if grapheme == " "
+            && trailing
+            && let Some(trail) = list_chars.and_then(|list_chars| list_chars.trail)
+        {
+            return whitespace(trail);
         }
-        if grapheme.width() == 0 {
-            return "·";
+        if (grapheme == "\u{a0}" || grapheme == "\u{202f}")
+            && let Some(nbsp) = list_chars.and_then(|list_chars| list_chars.nbsp)
+        {
+            return whitespace(nbsp);
+        }
+        let first_char = grapheme.chars().next().unwrap_or(' ');
+        if first_char.is_control() {
+            return (
+                Self::get_caret_notation(first_char).into(),
+                Some(HighlightGroup::NonText),
+            );
+        }
+        if grapheme.display_width() == 0 {
+            return match list_chars.and_then(|list_chars| list_chars.zero_width) {
+                Some(zero_width) => whitespace(zero_width),
+                None => (grapheme.into(), None),
+            };
+        }
+        (grapheme.into(), None)
+    }
+
+    // `^A` for C0 controls and `^?` for DEL, like most terminal programs, `<80>` for C1 controls
+    fn get_caret_notation(c: char) -> String {
+        match c as u32 {
+            code @ 0..0x20 => format!("^{}", char::from(code as u8 + 0x40)),
+            0x7f => "^?".into(),
+            code => format!("<{code:02x}>"),
         }
-        return grapheme;
     }
 
     fn scroll_cursor_into_view(&mut self, size: TerminalSize) {
-        let RenderPosition { col, row } = self.get_render_position_of_cursor();
         if size.width == 0 || size.height == 0 {
             return;
         }
+        self.width = size.width as usize;
+        let RenderPosition { col, row } = self.get_render_position_of_cursor();
+        let height = size.height as usize;
+        if self.wrap.is_some() {
+            self.origin.col = 0;
+            let cursor_row_idx = self.get_renderable_line(row).map_or(0, |line| {
+                Self::find_screen_row(&self.get_screen_rows(&line), col)
+            });
+            if row < self.origin.row {
+                self.origin.row = row;
+                self.skipped_rows = (cursor_row_idx + 1).saturating_sub(height);
+                return;
+            }
+            if row == self.origin.row && cursor_row_idx < self.skipped_rows {
+                self.skipped_rows = cursor_row_idx;
+                return;
+            }
+            // Walk up from the cursor's line while the lines above still fit with it, counting
+            // the rows each one takes, so each line is laid out once
+            let count_rows = |line_idx: usize| {
+                self.get_renderable_line(line_idx)
+                    .map_or(1, |line| self.get_screen_rows(&line).len())
+            };
+            let mut used_rows = cursor_row_idx + 1;
+            let mut top = row;
+            while top > self.origin.row {
+                let rows = count_rows(top - 1);
+                if used_rows + rows > height {
+                    break;
+                }
+                used_rows += rows;
+                top -= 1;
+            }
+            if top != self.origin.row {
+                self.origin.row = top;
+                self.skipped_rows = 0;
+            }
+            // The cursor's line alone doesn't fit, so its rows above the cursor are scrolled off
+            if top == row && used_rows > height {
+                self.skipped_rows = self.skipped_rows.max(used_rows - height);
+            }
+            return;
+        }
+        self.skipped_rows = 0;
         if col >= size.width as usize + self.origin.col {
             self.origin.col = col.saturating_sub(size.width as usize - 1);
         } else if col < self.origin.col {
             self.origin.col = col;
         }
-        if row >= size.height as usize + self.origin.row {
-            self.origin.row = row.saturating_sub(size.height as usize - 1);
+        if row >= height + self.origin.row {
+            self.origin.row = row.saturating_sub(height - 1);
         } else if row < self.origin.row {
             self.origin.row = row;
         }
     }
 
+    // The position of the cursor relative to the top left corner of the textarea
+    fn get_surface_position_of_cursor(&self) -> RenderPosition {
+        let RenderPosition { col, row } = self.get_render_position_of_cursor();
+        let Some(line) = self.get_renderable_line(row) else {
+            return RenderPosition::default();
+        };
+        let rows = self.get_screen_rows(&line);
+        let row_idx = Self::find_screen_row(&rows, col);
+        let rows_above: usize = if self.wrap.is_some() {
+            (self.origin.row..row)
+                .filter_map(|line_idx| self.get_renderable_line(line_idx))
+                .map(|line| self.get_screen_rows(&line).len())
+                .sum()
+        } else {
+            row.saturating_sub(self.origin.row)
+        };
+        RenderPosition {
+            col: col.saturating_sub(rows[row_idx].start_col)
+                + self.get_prefix_width(&rows[row_idx]),
+            row: (rows_above + row_idx).saturating_sub(self.skipped_rows),
+        }
+    }
+
     fn get_render_position_of_cursor(&self) -> RenderPosition {
         let GraphemeLocation { offset, line } = self.buffer.get_grapheme_location();
-        let cur_line = self.get_renderable_line(line).unwrap_or("".into());
-        let prev_graphemes = cur_line.graphemes(true).take(offset);
-        let col: usize = prev_graphemes.map(|grapheme| grapheme.width()).sum();
-        RenderPosition { col, row: line }
+        let cur_line = self.buffer.get_line(line).unwrap_or_default();
+        RenderPosition {
+            col: self.get_render_col(&cur_line, offset),
+            row: line,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wrapped_textarea(content: &str, width: usize) -> Textarea {
+        let mut options = Options::default();
+        for assignment in ["wrap", "linebreak", "showbreak=>>>"] {
+            options.set(assignment).unwrap();
+        }
+        let mut textarea = Textarea::new(content);
+        textarea.apply_options(&options);
+        textarea.width = width;
+        textarea
+    }
+
+    #[test]
+    fn continuation_rows_leave_room_for_showbreak() {
+        let textarea = wrapped_textarea("a bbbbbbbbbbbbbbbbbbbb", 10);
+        let line = textarea.get_renderable_line(0).unwrap();
+        let rows = textarea.get_screen_rows(&line);
+        let widths: Vec<usize> = rows.iter().map(|row| row.end_col - row.start_col).collect();
+        assert_eq!(widths, [2, 7, 7, 6]);
+    }
+
+    #[test]
+    fn scrolling_keeps_the_cursor_on_screen() {
+        // Every line takes two rows
+        let content = ["word word word"; 5].join("\n");
+        let mut textarea = wrapped_textarea(&content, 10);
+        textarea.buffer.move_grapheme_to_end_of_line(4);
+        let size = TerminalSize {
+            width: 10,
+            height: 6,
+        };
+        textarea.scroll_cursor_into_view(size);
+        assert_eq!(textarea.origin.row, 2);
+        assert_eq!(textarea.get_surface_position_of_cursor().row, 5);
+    }
+
+    #[test]
+    fn scrolling_within_a_line_taller_than_the_textarea() {
+        let mut textarea = wrapped_textarea(&["word"; 20].join(" "), 10);
+        let line = textarea.get_renderable_line(0).unwrap();
+        let row_count = textarea.get_screen_rows(&line).len();
+        let size = TerminalSize {
+            width: 10,
+            height: 3,
+        };
+        textarea.buffer.move_grapheme_to_end_of_line(0);
+        textarea.scroll_cursor_into_view(size);
+        assert_eq!(textarea.skipped_rows, row_count - 3);
+        assert_eq!(textarea.get_surface_position_of_cursor().row, 2);
+
+        textarea.buffer.move_grapheme_to_start_of_line(0);
+        textarea.scroll_cursor_into_view(size);
+        assert_eq!(textarea.skipped_rows, 0);
+        assert_eq!(textarea.get_surface_position_of_cursor().row, 0);
     }
 }
@@ -0,0 +1,150 @@
+use super::{DrawingSurface, sink::Sink};
+
+// How much of the space along a layout's direction a part takes
+#[derive(Clone, Copy)]
+pub enum Constraint {
+    // Exactly this many rows or columns
+    Fixed(u16),
+    // This percentage of the whole space
+    Percentage(u16),
+    // At least this many, and a share of what's left like `Fill`
+    Min(u16),
+    // A share of what's left like `Fill`, but no more than this many
+    Max(u16),
+    // An equal share of what's left once the other constraints are met
+    Fill,
+}
+
+#[derive(Clone, Copy)]
+enum LayoutDirection {
+    Rows,
+    Columns,
+}
+
+// Divides a surface into parts stacked from top to bottom or from left to right
+// When the fixed sizes don't all fit, the parts that come first are shrunk first, so that
+// e.g. a status line after the content keeps its size
+pub struct Layout<const N: usize> {
+    direction: LayoutDirection,
+    constraints: [Constraint; N],
+}
+
+impl<const N: usize> Layout<N> {
+    pub fn rows(constraints: [Constraint; N]) -> Layout<N> {
+        Layout {
+            direction: LayoutDirection::Rows,
+            constraints,
+        }
+    }
+
+    pub fn columns(constraints: [Constraint; N]) -> Layout<N> {
+        Layout {
+            direction: LayoutDirection::Columns,
+            constraints,
+        }
+    }
+
+    // The size of each part when there are `total` rows or columns to divide
+    pub fn get_sizes(&self, total: u16) -> [u16; N] {
+        let mut sizes = self.constraints.map(|constraint| match constraint {
+            Constraint::Fixed(size) | Constraint::Min(size) => size,
+            Constraint::Percentage(percentage) => {
+                (total as u32 * percentage.min(100) as u32 / 100) as u16
+            }
+            Constraint::Max(_) | Constraint::Fill => 0,
+        });
+
+        let mut excess = sizes.iter().map(|&size| size as u32).sum::<u32>();
+        excess = excess.saturating_sub(total as u32);
+        for size in sizes.iter_mut() {
+            let shrink = (*size as u32).min(excess);
+            *size -= shrink as u16;
+            excess -= shrink;
+        }
+
+        // Share what's left between the flexible parts, a part that reaches its maximum
+        // leaving the rest of its share to the others
+        let mut left = total - sizes.iter().sum::<u16>();
+        let mut growing: Vec<usize> = (0..N)
+            .filter(|&idx| {
+                matches!(
+                    self.constraints[idx],
+                    Constraint::Min(_) | Constraint::Max(_) | Constraint::Fill
+                )
+            })
+            .collect();
+        while left > 0 && !growing.is_empty() {
+            let share = (left / growing.len() as u16).max(1);
+            for &idx in &growing {
+                let limit = match self.constraints[idx] {
+                    Constraint::Max(max) => max,
+                    _ => u16::MAX,
+                };
+                let grow = share.min(left).min(limit - sizes[idx]);
+                sizes[idx] += grow;
+                left -= grow;
+            }
+            growing.retain(|&idx| match self.constraints[idx] {
+                Constraint::Max(max) => sizes[idx] < max,
+                _ => true,
+            });
+        }
+        sizes
+    }
+
+    // Divide the surface into one surface per constraint, in order
+    // Parts with no room get a surface that draws nothing
+    pub fn split(&self, surface: &dyn DrawingSurface) -> [Box<dyn DrawingSurface>; N] {
+        let size = surface.get_bounding_rect_size();
+        let total = match self.direction {
+            LayoutDirection::Rows => size.height,
+            LayoutDirection::Columns => size.width,
+        };
+        let sizes = self.get_sizes(total);
+        let mut rest: Option<Box<dyn DrawingSurface>> = None;
+        std::array::from_fn(|idx| {
+            if sizes[idx] == 0 {
+                return Box::new(Sink) as Box<dyn DrawingSurface>;
+            }
+            let remaining = rest.as_deref().unwrap_or(surface);
+            let (part, next_rest) = match self.direction {
+                LayoutDirection::Rows => remaining.slice_top_horizontal(sizes[idx]),
+                LayoutDirection::Columns => remaining.slice_left_vertical(sizes[idx]),
+            };
+            rest = Some(next_rest);
+            part
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn get_sizes_shrinks_the_first_parts_on_overflow() {
+        let layout = Layout::rows([Constraint::Fixed(5), Constraint::Fill, Constraint::Fixed(3)]);
+        assert_eq!(layout.get_sizes(6), [3, 0, 3]);
+        assert_eq!(layout.get_sizes(2), [0, 0, 2]);
+    }
+
+    #[test]
+    fn get_sizes_caps_max_and_gives_the_rest_to_fill() {
+        let layout = Layout::columns([Constraint::Max(4), Constraint::Fill]);
+        assert_eq!(layout.get_sizes(80), [4, 76]);
+        assert_eq!(layout.get_sizes(3), [2, 1]);
+        let layout = Layout::columns([
+            Constraint::Percentage(50),
+            Constraint::Fixed(1),
+            Constraint::Fill,
+        ]);
+        assert_eq!(layout.get_sizes(81), [40, 1, 40]);
+    }
+
+    #[test]
+    fn get_sizes_keeps_the_last_row_on_a_one_row_screen() {
+        let layout = Layout::rows([Constraint::Fixed(1), Constraint::Fill, Constraint::Fixed(1)]);
+        assert_eq!(layout.get_sizes(1), [0, 0, 1]);
+        assert_eq!(layout.get_sizes(0), [0, 0, 0]);
+    }
+}
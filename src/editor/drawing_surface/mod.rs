@@ -1,14 +1,32 @@
-use crate::editor::utility::{Style, TerminalPosition, TerminalSize};
+use crate::editor::{
+    theme::HighlightGroup,
+    utility::{CursorShape, TerminalPosition, TerminalSize},
+};
 
+pub mod layout;
+pub mod popup;
 pub mod rect;
 pub mod sink;
 
 pub trait DrawingSurface {
-    fn add_styles(&mut self, styles: Vec<Style>, start: TerminalPosition, end: TerminalPosition);
+    // Where highlights overlap the one with the highest priority wins, `None` giving the
+    // group's own priority
+    fn add_highlight(
+        &mut self,
+        group: HighlightGroup,
+        priority: Option<u8>,
+        start: TerminalPosition,
+        end: TerminalPosition,
+    );
     fn add_content(&mut self, content: &str, origin: TerminalPosition);
     fn add_cursor(&mut self, position: TerminalPosition);
+    fn set_cursor_shape(&mut self, shape: CursorShape);
     fn get_bounding_rect_size(&self) -> TerminalSize;
+    // Open a popup over everything else with its top-left corner at `origin`, relative to this
+    // surface, and room for `size` inside its border
+    fn add_popup(&self, origin: TerminalPosition, size: TerminalSize) -> Box<dyn DrawingSurface>;
 
+    #[allow(dead_code)]
     fn slice_bottom_horizontal(
         &self,
         rows: u16,
@@ -16,6 +34,7 @@ pub trait DrawingSurface {
     fn slice_top_horizontal(&self, rows: u16)
     -> (Box<dyn DrawingSurface>, Box<dyn DrawingSurface>);
     fn slice_left_vertical(&self, cols: u16) -> (Box<dyn DrawingSurface>, Box<dyn DrawingSurface>);
+    #[allow(dead_code)]
     fn slice_right_vertical(&self, cols: u16)
     -> (Box<dyn DrawingSurface>, Box<dyn DrawingSurface>);
 }
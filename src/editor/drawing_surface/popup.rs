@@ -0,0 +1,143 @@
+use super::{DrawingSurface, rect::Rect, sink::Sink};
+use crate::editor::{
+    canvas::Canvas,
+    theme::HighlightGroup,
+    utility::{CursorShape, TerminalArea, TerminalPosition, TerminalSize},
+};
+use std::{cell::RefCell, rc::Rc};
+
+// A floating window with a border, drawn on a canvas layer of its own so that it hides what's
+// beneath it, which shows again once the popup is no longer drawn
+// Drawing on it draws inside the border
+pub struct Popup {
+    content: Box<dyn DrawingSurface>,
+}
+
+impl Popup {
+    // A popup with room for `size` inside its border, with its top-left corner at `origin`,
+    // or as close to it as fits on the screen
+    pub fn new(canvas: Rc<RefCell<Canvas>>, origin: TerminalPosition, size: TerminalSize) -> Popup {
+        let screen = canvas.borrow().get_size();
+        let width = size.width.saturating_add(2).min(screen.width);
+        let height = size.height.saturating_add(2).min(screen.height);
+        if width < 3 || height < 3 {
+            return Popup {
+                content: Box::new(Sink),
+            };
+        }
+        let area = TerminalArea::new(
+            TerminalPosition {
+                col: origin.col.min(screen.width - width),
+                row: origin.row.min(screen.height - height),
+            },
+            TerminalSize { width, height },
+        );
+        let layer = canvas.borrow_mut().add_layer(area);
+        Self::draw_border(&mut Rect::on_layer(canvas.clone(), layer, area));
+        let content_area = TerminalArea {
+            top: area.top + 1,
+            left: area.left + 1,
+            bottom: area.bottom - 1,
+            right: area.right - 1,
+        };
+        Popup {
+            content: Box::new(Rect::on_layer(canvas, layer, content_area)),
+        }
+    }
+
+    fn draw_border(frame: &mut Rect) {
+        let TerminalSize { width, height } = frame.get_bounding_rect_size();
+        let horizontal = "─".repeat(width as usize - 2);
+        for row in 0..height {
+            let (start, end) = (
+                TerminalPosition { col: 0, row },
+                TerminalPosition { col: width, row },
+            );
+            frame.add_highlight(HighlightGroup::NormalFloat, None, start, end);
+            if row == 0 || row == height - 1 {
+                frame.add_highlight(HighlightGroup::FloatBorder, None, start, end);
+            } else {
+                frame.add_highlight(
+                    HighlightGroup::FloatBorder,
+                    None,
+                    start,
+                    TerminalPosition { col: 1, row },
+                );
+                frame.add_highlight(
+                    HighlightGroup::FloatBorder,
+                    None,
+                    TerminalPosition {
+                        col: width - 1,
+                        row,
+                    },
+                    end,
+                );
+            }
+            let line = if row == 0 {
+                format!("┌{horizontal}┐")
+            } else if row == height - 1 {
+                format!("└{horizontal}┘")
+            } else {
+                format!("│{}│", " ".repeat(width as usize - 2))
+            };
+            frame.add_content(&line, start);
+        }
+    }
+}
+
+impl DrawingSurface for Popup {
+    fn add_highlight(
+        &mut self,
+        group: HighlightGroup,
+        priority: Option<u8>,
+        start: TerminalPosition,
+        end: TerminalPosition,
+    ) {
+        self.content.add_highlight(group, priority, start, end);
+    }
+
+    fn add_content(&mut self, content: &str, origin: TerminalPosition) {
+        self.content.add_content(content, origin);
+    }
+
+    fn add_cursor(&mut self, position: TerminalPosition) {
+        self.content.add_cursor(position);
+    }
+
+    fn set_cursor_shape(&mut self, shape: CursorShape) {
+        self.content.set_cursor_shape(shape);
+    }
+
+    fn get_bounding_rect_size(&self) -> TerminalSize {
+        self.content.get_bounding_rect_size()
+    }
+
+    fn add_popup(&self, origin: TerminalPosition, size: TerminalSize) -> Box<dyn DrawingSurface> {
+        self.content.add_popup(origin, size)
+    }
+
+    fn slice_bottom_horizontal(
+        &self,
+        rows: u16,
+    ) -> (Box<dyn DrawingSurface>, Box<dyn DrawingSurface>) {
+        self.content.slice_bottom_horizontal(rows)
+    }
+
+    fn slice_top_horizontal(
+        &self,
+        rows: u16,
+    ) -> (Box<dyn DrawingSurface>, Box<dyn DrawingSurface>) {
+        self.content.slice_top_horizontal(rows)
+    }
+
+    fn slice_left_vertical(&self, cols: u16) -> (Box<dyn DrawingSurface>, Box<dyn DrawingSurface>) {
+        self.content.slice_left_vertical(cols)
+    }
+
+    fn slice_right_vertical(
+        &self,
+        cols: u16,
+    ) -> (Box<dyn DrawingSurface>, Box<dyn DrawingSurface>) {
+        self.content.slice_right_vertical(cols)
+    }
+}
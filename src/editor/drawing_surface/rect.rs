@@ -1,36 +1,57 @@
-use super::{DrawingSurface, sink::Sink};
+use super::{DrawingSurface, popup::Popup, sink::Sink};
 use crate::editor::{
-    canvas::Canvas,
-    utility::{Style, TerminalArea, TerminalPosition, TerminalSize},
+    canvas::{Canvas, LayerId},
+    theme::HighlightGroup,
+    utility::{CursorShape, TerminalArea, TerminalPosition, TerminalSize},
 };
 use std::{cell::RefCell, rc::Rc};
-use unicode_segmentation::UnicodeSegmentation;
-use unicode_width::UnicodeWidthStr;
 
 #[derive(Clone)]
 pub struct Rect {
     canvas: Rc<RefCell<Canvas>>,
+    // The canvas layer drawn on, the canvas itself if none
+    layer: Option<LayerId>,
     area: TerminalArea,
 }
 
 impl Rect {
-    pub fn new(canvas: Rc<RefCell<Canvas>>, area: TerminalArea) -> Rect {
-        Rect { canvas, area }
-    }
-
     pub fn from_canvas(canvas: Rc<RefCell<Canvas>>) -> Rect {
         Rect {
             canvas: canvas.clone(),
+            layer: None,
             area: TerminalArea::new(
                 TerminalPosition { col: 0, row: 0 },
                 canvas.borrow().get_size(),
             ),
         }
     }
+
+    pub fn on_layer(canvas: Rc<RefCell<Canvas>>, layer: LayerId, area: TerminalArea) -> Rect {
+        Rect {
+            canvas,
+            layer: Some(layer),
+            area,
+        }
+    }
+
+    // A part of this rect, on the same layer
+    fn get_sub_rect(&self, area: TerminalArea) -> Box<dyn DrawingSurface> {
+        Box::new(Rect {
+            canvas: self.canvas.clone(),
+            layer: self.layer,
+            area,
+        })
+    }
 }
 
 impl DrawingSurface for Rect {
-    fn add_styles(&mut self, styles: Vec<Style>, start: TerminalPosition, end: TerminalPosition) {
+    fn add_highlight(
+        &mut self,
+        group: HighlightGroup,
+        priority: Option<u8>,
+        start: TerminalPosition,
+        end: TerminalPosition,
+    ) {
         let max_width = self.area.get_width();
         let max_height = self.area.get_height();
         if start.col >= max_width || start.row >= max_height {
@@ -44,9 +65,11 @@ impl DrawingSurface for Rect {
             row: start.row.min(max_height - 1),
             col: start.col.min(max_width - 1),
         };
+        // The end column is exclusive, so a highlight may reach up to the width to cover the
+        // last column
         let clamped_end = TerminalPosition {
             row: end.row.min(max_height - 1),
-            col: end.col.min(max_width - 1),
+            col: end.col.min(max_width),
         };
 
         let canvas_start = TerminalPosition {
@@ -58,36 +81,30 @@ impl DrawingSurface for Rect {
             col: clamped_end.col + left,
         };
 
-        self.canvas
-            .borrow_mut()
-            .add_styles(styles, canvas_start, canvas_end);
+        self.canvas.borrow_mut().add_highlight(
+            self.layer,
+            group,
+            priority,
+            canvas_start,
+            canvas_end,
+        );
     }
 
     fn add_content(&mut self, content: &str, origin: TerminalPosition) {
-        let max_width = self.area.get_width();
-        let max_height = self.area.get_height();
-        if origin.col >= max_width && origin.row >= max_height {
+        if origin.col >= self.area.get_width() || origin.row >= self.area.get_height() {
             return;
         }
         let left = self.area.get_left();
         let top = self.area.get_top();
 
-        let mut cur_width = origin.col as usize;
-        let truncated_line = content
-            .graphemes(true)
-            .take_while(|grapheme| {
-                cur_width += grapheme.width();
-                cur_width < max_width.into()
-            })
-            .collect::<Vec<_>>()
-            .join("");
-
         self.canvas.borrow_mut().add_content(
-            &truncated_line,
+            self.layer,
+            content,
             TerminalPosition {
                 row: origin.row + top,
                 col: origin.col + left,
             },
+            self.area.get_right() + 1,
         );
     }
 
@@ -98,6 +115,10 @@ impl DrawingSurface for Rect {
         });
     }
 
+    fn set_cursor_shape(&mut self, shape: CursorShape) {
+        self.canvas.borrow_mut().set_cursor_shape(shape);
+    }
+
     fn get_bounding_rect_size(&self) -> TerminalSize {
         TerminalSize {
             width: self.area.get_width(),
@@ -105,32 +126,37 @@ impl DrawingSurface for Rect {
         }
     }
 
+    fn add_popup(&self, origin: TerminalPosition, size: TerminalSize) -> Box<dyn DrawingSurface> {
+        Box::new(Popup::new(
+            self.canvas.clone(),
+            TerminalPosition {
+                col: origin.col.saturating_add(self.area.get_left()),
+                row: origin.row.saturating_add(self.area.get_top()),
+            },
+            size,
+        ))
+    }
+
     fn slice_bottom_horizontal(
         &self,
         rows: u16,
     ) -> (Box<dyn DrawingSurface>, Box<dyn DrawingSurface>) {
-        if self.area.get_height() < rows {
-            return (Box::new(Sink::default()), Box::new(self.clone()));
+        if self.area.get_height() <= rows {
+            return (Box::new(Sink), Box::new(self.clone()));
         }
         (
-            Box::new(Rect::new(
-                self.canvas.clone(),
-                TerminalArea {
-                    top: self.area.get_top(),
-                    left: self.area.get_left(),
-                    bottom: self.area.get_bottom() - rows,
-                    right: self.area.get_right(),
-                },
-            )),
-            Box::new(Rect::new(
-                self.canvas.clone(),
-                TerminalArea {
-                    top: self.area.get_bottom() - rows + 1,
-                    left: self.area.get_left(),
-                    bottom: self.area.get_bottom(),
-                    right: self.area.get_right(),
-                },
-            )),
+            self.get_sub_rect(TerminalArea {
+                top: self.area.get_top(),
+                left: self.area.get_left(),
+                bottom: self.area.get_bottom() - rows,
+                right: self.area.get_right(),
+            }),
+            self.get_sub_rect(TerminalArea {
+                top: self.area.get_bottom() - rows + 1,
+                left: self.area.get_left(),
+                bottom: self.area.get_bottom(),
+                right: self.area.get_right(),
+            }),
         )
     }
 
@@ -138,54 +164,45 @@ impl DrawingSurface for Rect {
         &self,
         rows: u16,
     ) -> (Box<dyn DrawingSurface>, Box<dyn DrawingSurface>) {
-        if self.area.get_height() < rows {
-            return (Box::new(self.clone()), Box::new(Sink::default()));
+        // Slicing off every row, as for the last part of a layout, leaves nothing below, and a
+        // rect of no rows can't be represented
+        if self.area.get_height() <= rows {
+            return (Box::new(self.clone()), Box::new(Sink));
         }
         (
-            Box::new(Rect::new(
-                self.canvas.clone(),
-                TerminalArea {
-                    top: self.area.get_top(),
-                    left: self.area.get_left(),
-                    bottom: self.area.get_top() + rows - 1,
-                    right: self.area.get_right(),
-                },
-            )),
-            Box::new(Rect::new(
-                self.canvas.clone(),
-                TerminalArea {
-                    top: self.area.get_top() + rows,
-                    left: self.area.get_left(),
-                    bottom: self.area.get_bottom(),
-                    right: self.area.get_right(),
-                },
-            )),
+            self.get_sub_rect(TerminalArea {
+                top: self.area.get_top(),
+                left: self.area.get_left(),
+                bottom: self.area.get_top() + rows - 1,
+                right: self.area.get_right(),
+            }),
+            self.get_sub_rect(TerminalArea {
+                top: self.area.get_top() + rows,
+                left: self.area.get_left(),
+                bottom: self.area.get_bottom(),
+                right: self.area.get_right(),
+            }),
         )
     }
 
     fn slice_left_vertical(&self, cols: u16) -> (Box<dyn DrawingSurface>, Box<dyn DrawingSurface>) {
-        if self.area.get_width() < cols {
-            return (Box::new(self.clone()), Box::new(Sink::default()));
+        // Likewise slicing off every column leaves nothing to the right
+        if self.area.get_width() <= cols {
+            return (Box::new(self.clone()), Box::new(Sink));
         }
         (
-            Box::new(Rect::new(
-                self.canvas.clone(),
-                TerminalArea {
-                    top: self.area.get_top(),
-                    left: self.area.get_left(),
-                    bottom: self.area.get_bottom(),
-                    right: self.area.get_left() + cols - 1,
-                },
-            )),
-            Box::new(Rect::new(
-                self.canvas.clone(),
-                TerminalArea {
-                    top: self.area.get_top(),
-                    left: self.area.get_left() + cols,
-                    bottom: self.area.get_bottom(),
-                    right: self.area.get_right(),
-                },
-            )),
+            self.get_sub_rect(TerminalArea {
+                top: self.area.get_top(),
+                left: self.area.get_left(),
+                bottom: self.area.get_bottom(),
+                right: self.area.get_left() + cols - 1,
+            }),
+            self.get_sub_rect(TerminalArea {
+                top: self.area.get_top(),
+                left: self.area.get_left() + cols,
+                bottom: self.area.get_bottom(),
+                right: self.area.get_right(),
+            }),
         )
     }
 
@@ -193,28 +210,22 @@ impl DrawingSurface for Rect {
         &self,
         cols: u16,
     ) -> (Box<dyn DrawingSurface>, Box<dyn DrawingSurface>) {
-        if self.area.get_width() < cols {
-            return (Box::new(Sink::default()), Box::new(self.clone()));
+        if self.area.get_width() <= cols {
+            return (Box::new(Sink), Box::new(self.clone()));
         }
         (
-            Box::new(Rect::new(
-                self.canvas.clone(),
-                TerminalArea {
-                    top: self.area.get_top(),
-                    left: self.area.get_left(),
-                    bottom: self.area.get_bottom(),
-                    right: self.area.get_right() - cols,
-                },
-            )),
-            Box::new(Rect::new(
-                self.canvas.clone(),
-                TerminalArea {
-                    top: self.area.get_top(),
-                    left: self.area.get_right() - cols + 1,
-                    bottom: self.area.get_bottom(),
-                    right: self.area.get_right(),
-                },
-            )),
+            self.get_sub_rect(TerminalArea {
+                top: self.area.get_top(),
+                left: self.area.get_left(),
+                bottom: self.area.get_bottom(),
+                right: self.area.get_right() - cols,
+            }),
+            self.get_sub_rect(TerminalArea {
+                top: self.area.get_top(),
+                left: self.area.get_right() - cols + 1,
+                bottom: self.area.get_bottom(),
+                right: self.area.get_right(),
+            }),
         )
     }
 }
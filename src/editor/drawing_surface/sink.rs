@@ -1,21 +1,27 @@
 use super::DrawingSurface;
-use crate::editor::utility::{Style, TerminalPosition, TerminalSize};
+use crate::editor::{
+    theme::HighlightGroup,
+    utility::{CursorShape, TerminalPosition, TerminalSize},
+};
 
 #[derive(Default, Clone)]
 pub struct Sink;
 
 impl DrawingSurface for Sink {
-    fn add_styles(&mut self, styles: Vec<Style>, start: TerminalPosition, end: TerminalPosition) {
-        return;
+    fn add_highlight(
+        &mut self,
+        _group: HighlightGroup,
+        _priority: Option<u8>,
+        _start: TerminalPosition,
+        _end: TerminalPosition,
+    ) {
     }
 
-    fn add_content(&mut self, content: &str, origin: TerminalPosition) {
-        return;
-    }
+    fn add_content(&mut self, _content: &str, _origin: TerminalPosition) {}
 
-    fn add_cursor(&mut self, position: TerminalPosition) {
-        return;
-    }
+    fn add_cursor(&mut self, _position: TerminalPosition) {}
+
+    fn set_cursor_shape(&mut self, _shape: CursorShape) {}
 
     fn get_bounding_rect_size(&self) -> TerminalSize {
         TerminalSize {
@@ -24,22 +30,35 @@ impl DrawingSurface for Sink {
         }
     }
 
+    fn add_popup(&self, _origin: TerminalPosition, _size: TerminalSize) -> Box<dyn DrawingSurface> {
+        Box::new(Sink)
+    }
+
     fn slice_bottom_horizontal(
         &self,
-        rows: u16,
+        _rows: u16,
     ) -> (Box<dyn DrawingSurface>, Box<dyn DrawingSurface>) {
         (Box::new(self.clone()), Box::new(self.clone()))
     }
 
-    fn slice_top_horizontal(&self, rows: u16) -> (Box<dyn DrawingSurface>, Box<dyn DrawingSurface>) {
+    fn slice_top_horizontal(
+        &self,
+        _rows: u16,
+    ) -> (Box<dyn DrawingSurface>, Box<dyn DrawingSurface>) {
         (Box::new(self.clone()), Box::new(self.clone()))
     }
 
-    fn slice_left_vertical(&self, cols: u16) -> (Box<dyn DrawingSurface>, Box<dyn DrawingSurface>) {
+    fn slice_left_vertical(
+        &self,
+        _cols: u16,
+    ) -> (Box<dyn DrawingSurface>, Box<dyn DrawingSurface>) {
         (Box::new(self.clone()), Box::new(self.clone()))
     }
 
-    fn slice_right_vertical(&self, cols: u16) -> (Box<dyn DrawingSurface>, Box<dyn DrawingSurface>) {
+    fn slice_right_vertical(
+        &self,
+        _cols: u16,
+    ) -> (Box<dyn DrawingSurface>, Box<dyn DrawingSurface>) {
         (Box::new(self.clone()), Box::new(self.clone()))
     }
 }
@@ -1,77 +1,142 @@
 use app::App;
 use canvas::Canvas;
-use crossterm::event::{Event, KeyModifiers, read};
+use crossterm::event::{Event, KeyEvent, KeyModifiers, poll, read};
 use drawing_surface::rect::Rect;
-use std::{cell::RefCell, io::Error, path::PathBuf, rc::Rc};
+use std::{
+    cell::RefCell,
+    io::Error,
+    panic::{self, AssertUnwindSafe, PanicHookInfo},
+    path::PathBuf,
+    rc::Rc,
+    sync::Arc,
+};
 use terminal::Terminal;
+use width::AmbiWidth;
 
 mod app;
 mod canvas;
+mod color;
 mod component;
 mod drawing_surface;
+mod options;
 mod terminal;
+mod theme;
 mod utility;
+mod width;
 
 #[derive(Default)]
 pub struct Editor;
 
 impl Editor {
-    pub fn run(&self, path: Option<PathBuf>) -> Result<(), Error> {
-        let mut core_editor = CoreEditor::new(path)?;
+    pub fn run(&self, paths: Vec<PathBuf>) -> Result<(), Error> {
+        let mut core_editor = CoreEditor::new(paths);
         core_editor.run()?;
         Ok(())
     }
 }
 
+// Restores the terminal when dropped, so the user gets a usable terminal back
+// however the editor exits
+struct TerminalGuard;
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let _ = Terminal::release();
+    }
+}
+
+type PanicHook = Box<dyn Fn(&PanicHookInfo) + Sync + Send>;
+
 struct CoreEditor {
     canvas: Rc<RefCell<Canvas>>,
     app: App,
+    // The panic hook installed before ours, put back when the editor exits
+    previous_hook: Option<Arc<PanicHook>>,
 }
 
 impl CoreEditor {
-    fn new(path: Option<PathBuf>) -> Result<CoreEditor, Error> {
-        Ok(CoreEditor {
+    fn new(paths: Vec<PathBuf>) -> CoreEditor {
+        CoreEditor {
             canvas: Rc::new(RefCell::new(Canvas::new())),
-            app: App::new(path)?,
-        })
+            app: App::new(paths),
+            previous_hook: None,
+        }
     }
 
     fn run(&mut self) -> Result<(), Error> {
-        self.init()?;
-        let result = self.repl();
-        self.finalize()?;
-        result
+        let _guard = self.init()?;
+        match panic::catch_unwind(AssertUnwindSafe(|| self.repl())) {
+            Ok(Ok(())) => self.finalize(),
+            Ok(Err(error)) => {
+                // Save the modified buffers before anything else can fail, and report where
+                // they went once the terminal is back
+                let results = self.app.write_recovery_files();
+                let _ = self.finalize();
+                Self::report_recovery_files(results);
+                Err(error)
+            }
+            Err(payload) => {
+                // The panic hook has already restored the terminal and reported the panic
+                Self::report_recovery_files(self.app.write_recovery_files());
+                panic::resume_unwind(payload)
+            }
+        }
     }
 
-    fn init(&mut self) -> Result<(), Error> {
-        Terminal::enter_alternate_screen()?;
-        Terminal::enable_raw_mode()?;
+    fn init(&mut self) -> Result<TerminalGuard, Error> {
+        let guard = TerminalGuard;
+        Terminal::acquire()?;
+        Terminal::detect_capabilities()?;
+        let capabilities = Terminal::get_capabilities();
+        self.app.set_terminal_colors(
+            capabilities.default_foreground,
+            capabilities.default_background,
+        );
         Terminal::clear_screen()?;
-        Ok(())
+
+        // Restore the terminal first so that the panic message is printed on the user's screen
+        let previous_hook = Arc::new(panic::take_hook());
+        self.previous_hook = Some(previous_hook.clone());
+        panic::set_hook(Box::new(move |info| {
+            let _ = Terminal::release();
+            previous_hook(info);
+        }));
+        Ok(guard)
     }
 
     fn finalize(&mut self) -> Result<(), Error> {
+        if let Some(previous_hook) = self.previous_hook.take() {
+            panic::set_hook(Box::new(move |info| previous_hook(info)));
+        }
         Terminal::clear_screen()?;
-        Terminal::disable_raw_mode()?;
-        Terminal::leave_alternate_screen()?;
+        Terminal::release()?;
         Ok(())
     }
 
+    fn report_recovery_files(results: Vec<Result<PathBuf, Error>>) {
+        for result in results {
+            match result {
+                Ok(path) => eprintln!("Modified buffer saved to {}", path.display()),
+                Err(error) => eprintln!("Failed to save a modified buffer: {error}"),
+            }
+        }
+    }
+
     fn repl(&mut self) -> Result<(), Error> {
         self.render_all()?;
+        for event in Terminal::take_pending_keys() {
+            if self.handle_key(event)? {
+                return Ok(());
+            }
+        }
 
         loop {
+            if !poll(self.app.get_idle_timeout())? {
+                self.app.handle_idle();
+                continue;
+            }
             match read()? {
-                Event::Key(event) => {
-                    if event.is_press()
-                        && event.code.is_char('q')
-                        && event.modifiers.contains(KeyModifiers::CONTROL)
-                    {
-                        return Ok(());
-                    }
-                    self.app.handle_key(event);
-                    self.render_incremental()?;
-                }
+                Event::Key(event) if self.handle_key(event)? => return Ok(()),
                 Event::Resize(_, _) => {
                     self.render_all()?;
                 }
@@ -80,20 +145,53 @@ impl CoreEditor {
         }
     }
 
+    // Handle a key and redraw, returning whether the editor is quitting
+    fn handle_key(&mut self, event: KeyEvent) -> Result<bool, Error> {
+        if event.is_press()
+            && event.code.is_char('q')
+            && event.modifiers.contains(KeyModifiers::CONTROL)
+        {
+            self.app.quit();
+        } else {
+            self.app.handle_key(event);
+        }
+        if self.app.should_quit() {
+            self.app.close();
+            return Ok(true);
+        }
+        self.render_incremental()?;
+        Ok(false)
+    }
+
     fn render_incremental(&mut self) -> Result<(), Error> {
         self.canvas.borrow_mut().clear();
-        let mut surface = Rect::from_canvas(self.canvas.clone());
-        self.app.draw(&mut surface);
-        self.app.focus(&mut surface);
+        self.sync_canvas_settings();
+        let surface = Rect::from_canvas(self.canvas.clone());
+        let mut frame = self.app.layout(Box::new(surface));
+        self.app.draw(&mut frame);
+        self.app.focus(&mut frame);
         self.canvas.borrow_mut().render_changes()?;
         Ok(())
     }
 
+    fn sync_canvas_settings(&mut self) {
+        let mut canvas = self.canvas.borrow_mut();
+        canvas.set_theme(self.app.get_theme());
+        canvas.set_color_depth(self.app.get_options().term_colors);
+        let mut width_model = Terminal::get_capabilities().width_model;
+        if let Some(ambiwidth) = self.app.get_options().ambiwidth {
+            width_model.ambiguous_wide = ambiwidth == AmbiWidth::Double;
+        }
+        canvas.set_width_model(width_model);
+    }
+
     fn render_all(&mut self) -> Result<(), Error> {
         self.canvas.borrow_mut().clear();
-        let mut surface = Rect::from_canvas(self.canvas.clone());
-        self.app.draw(&mut surface);
-        self.app.focus(&mut surface);
+        self.sync_canvas_settings();
+        let surface = Rect::from_canvas(self.canvas.clone());
+        let mut frame = self.app.layout(Box::new(surface));
+        self.app.draw(&mut frame);
+        self.app.focus(&mut frame);
         self.canvas.borrow_mut().render_all()?;
         Ok(())
     }
@@ -0,0 +1,430 @@
+use super::{
+    color::ColorDepth,
+    component::statusline::StatusLine,
+    theme::Background,
+    utility::CursorShape,
+    width::{AmbiWidth, DisplayWidth},
+};
+
+// The cursor shape to use in each editor mode
+#[derive(Clone, Copy)]
+pub struct CursorShapes {
+    pub normal: CursorShape,
+    pub insert: CursorShape,
+    pub replace: CursorShape,
+    pub command: CursorShape,
+}
+
+impl Default for CursorShapes {
+    fn default() -> CursorShapes {
+        CursorShapes {
+            normal: CursorShape::Block,
+            insert: CursorShape::Bar,
+            replace: CursorShape::Underline,
+            command: CursorShape::Bar,
+        }
+    }
+}
+
+impl CursorShapes {
+    // Parse a comma-separated list of `modes:shape` entries, e.g. `n:block,i-c:bar,r:underline`
+    // Modes not mentioned in the list keep their current shape
+    fn parse(&self, value: &str) -> Result<CursorShapes, String> {
+        let mut shapes = *self;
+        for entry in value.split(',').filter(|entry| !entry.is_empty()) {
+            let (modes, shape) = entry
+                .split_once(':')
+                .ok_or(format!("Invalid cursor shape entry: {entry}"))?;
+            let shape =
+                CursorShape::parse(shape).ok_or(format!("Unknown cursor shape: {shape}"))?;
+            for mode in modes.split('-') {
+                match mode {
+                    "n" => shapes.normal = shape,
+                    "i" => shapes.insert = shape,
+                    "r" => shapes.replace = shape,
+                    "c" => shapes.command = shape,
+                    _ => return Err(format!("Unknown mode: {mode}")),
+                }
+            }
+        }
+        Ok(shapes)
+    }
+}
+
+// How tabs are displayed and how Tab, Backspace, Ctrl-T and Ctrl-D indent in Insert mode
+#[derive(Clone, Copy)]
+pub struct TabOptions {
+    // The number of columns between tabstops a tab character is displayed up to
+    pub tabstop: usize,
+    // The number of columns Ctrl-T and Ctrl-D indent by, 0 to use `tabstop`
+    pub shiftwidth: usize,
+    // The number of columns Tab inserts and Backspace deletes in whitespace, 0 to use `tabstop`
+    pub softtabstop: usize,
+    // Whether Tab inserts spaces rather than tab characters
+    pub expandtab: bool,
+}
+
+impl Default for TabOptions {
+    fn default() -> TabOptions {
+        TabOptions {
+            tabstop: 8,
+            shiftwidth: 8,
+            softtabstop: 0,
+            expandtab: false,
+        }
+    }
+}
+
+impl TabOptions {
+    pub fn get_shiftwidth(&self) -> usize {
+        if self.shiftwidth == 0 {
+            self.tabstop
+        } else {
+            self.shiftwidth
+        }
+    }
+
+    pub fn get_softtabstop(&self) -> usize {
+        if self.softtabstop == 0 {
+            self.tabstop
+        } else {
+            self.softtabstop
+        }
+    }
+
+    // The number of columns a tab character starting at `col` is displayed with
+    pub fn get_tab_width(&self, col: usize) -> usize {
+        self.tabstop - col % self.tabstop
+    }
+}
+
+// The glyphs a tab is drawn with in list mode: `first`, then `fill` up to the tabstop,
+// ending with `last` if given
+#[derive(Clone, Copy)]
+pub struct TabGlyphs {
+    pub first: char,
+    pub fill: char,
+    pub last: Option<char>,
+}
+
+impl TabGlyphs {
+    pub fn render(&self, width: usize) -> String {
+        let Some(last) = self.last else {
+            return std::iter::once(self.first)
+                .chain(std::iter::repeat_n(self.fill, width.saturating_sub(1)))
+                .collect();
+        };
+        if width <= 1 {
+            return last.into();
+        }
+        std::iter::once(self.first)
+            .chain(std::iter::repeat_n(self.fill, width - 2))
+            .chain(std::iter::once(last))
+            .collect()
+    }
+}
+
+// The glyphs list mode draws invisible characters with, `None` leaves the character as is
+#[derive(Clone, Copy)]
+pub struct ListChars {
+    pub tab: Option<TabGlyphs>,
+    // Spaces at the end of a line
+    pub trail: Option<char>,
+    // Non-breaking spaces
+    pub nbsp: Option<char>,
+    // Drawn after the last character of each line
+    pub eol: Option<char>,
+    // Characters that would otherwise take no column at all
+    pub zero_width: Option<char>,
+}
+
+impl Default for ListChars {
+    fn default() -> ListChars {
+        ListChars {
+            tab: Some(TabGlyphs {
+                first: '>',
+                fill: ' ',
+                last: None,
+            }),
+            trail: Some('-'),
+            nbsp: Some('+'),
+            eol: None,
+            zero_width: Some('·'),
+        }
+    }
+}
+
+impl ListChars {
+    // Parse a comma-separated list of `name:glyphs` entries, e.g. `tab:>-,trail:~,eol:$,zw:·`
+    // Only the glyphs mentioned are shown
+    fn parse(value: &str) -> Result<ListChars, String> {
+        let mut list_chars = ListChars {
+            tab: None,
+            trail: None,
+            nbsp: None,
+            eol: None,
+            zero_width: None,
+        };
+        for entry in value.split(',').filter(|entry| !entry.is_empty()) {
+            let (name, glyphs) = entry
+                .split_once(':')
+                .ok_or(format!("Invalid listchars entry: {entry}"))?;
+            // Each glyph must take exactly one column, or the text after it would shift
+            let glyphs: Vec<char> = glyphs.chars().collect();
+            if glyphs
+                .iter()
+                .any(|glyph| glyph.to_string().display_width() != 1)
+            {
+                return Err(format!("Glyphs must be one column wide: {entry}"));
+            }
+            let single = || match glyphs[..] {
+                [glyph] => Ok(Some(glyph)),
+                _ => Err(format!("Expected a single glyph: {entry}")),
+            };
+            match name {
+                "tab" => {
+                    list_chars.tab = match glyphs[..] {
+                        [first, fill] => Some(TabGlyphs {
+                            first,
+                            fill,
+                            last: None,
+                        }),
+                        [first, fill, last] => Some(TabGlyphs {
+                            first,
+                            fill,
+                            last: Some(last),
+                        }),
+                        _ => return Err(format!("Expected two or three glyphs: {entry}")),
+                    }
+                }
+                "trail" => list_chars.trail = single()?,
+                "nbsp" => list_chars.nbsp = single()?,
+                "eol" => list_chars.eol = single()?,
+                "zw" => list_chars.zero_width = single()?,
+                _ => return Err(format!("Unknown listchars entry: {name}")),
+            }
+        }
+        Ok(list_chars)
+    }
+}
+
+// How long lines are wrapped when soft wrapping is on
+#[derive(Clone)]
+pub struct WrapOptions {
+    // Whether lines are broken at word boundaries rather than at the last column that fits
+    pub linebreak: bool,
+    // Drawn at the start of every row a line is continued on
+    pub showbreak: String,
+}
+
+// Which line numbers the gutter shows: the `number` and `relativenumber` options together
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub enum LineNumbers {
+    Off,
+    Absolute,
+    Relative,
+    // Relative numbers, except for the cursor line which shows its absolute number
+    Hybrid,
+}
+
+// When the sign column is shown
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub enum SignColumn {
+    // Only while the buffer has signs
+    Auto,
+    Yes,
+    No,
+}
+
+#[derive(Clone)]
+pub struct Options {
+    pub cursor_shapes: CursorShapes,
+    // Milliseconds without typing after which swap files are written
+    pub update_time: u64,
+    // Number of edits after which swap files are written, 0 to only write them when idle
+    pub update_count: usize,
+    // The color depth to render with, `None` to use the one detected from the environment
+    pub term_colors: Option<ColorDepth>,
+    // Whether the terminal background is dark or light, `None` to detect it from the terminal
+    pub background: Option<Background>,
+    // Whether East Asian Ambiguous characters are double width, `None` to detect it from the
+    // terminal
+    pub ambiwidth: Option<AmbiWidth>,
+    pub tabs: TabOptions,
+    // Whether invisible characters are drawn with the glyphs of `list_chars`
+    pub list: bool,
+    pub list_chars: ListChars,
+    // Whether long lines are wrapped onto the following rows rather than scrolled horizontally
+    pub wrap: bool,
+    pub linebreak: bool,
+    pub showbreak: String,
+    pub number: bool,
+    pub relative_number: bool,
+    pub sign_column: SignColumn,
+    // The status line, parsed from its format when the option is set
+    pub status_line: StatusLine,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            cursor_shapes: CursorShapes::default(),
+            update_time: 4000,
+            update_count: 200,
+            term_colors: None,
+            background: None,
+            ambiwidth: None,
+            tabs: TabOptions::default(),
+            list: false,
+            list_chars: ListChars::default(),
+            wrap: false,
+            linebreak: false,
+            showbreak: String::new(),
+            number: false,
+            relative_number: false,
+            sign_column: SignColumn::Auto,
+            status_line: StatusLine::default(),
+        }
+    }
+}
+
+impl Options {
+    // Apply an assignment of the form `name=value`, or `name`/`noname` for a boolean option
+    pub fn set(&mut self, assignment: &str) -> Result<(), String> {
+        let Some((name, value)) = assignment.split_once('=') else {
+            return self.set_flag(assignment);
+        };
+        match name.trim() {
+            "cursorshape" => self.cursor_shapes = self.cursor_shapes.parse(value.trim())?,
+            "updatetime" => {
+                // The idle timeout would be zero, so the editor would spin writing swap files
+                let update_time = Self::parse_number(value)?;
+                if update_time == 0 {
+                    return Err("Argument must be positive: updatetime".into());
+                }
+                self.update_time = update_time;
+            }
+            "updatecount" => self.update_count = Self::parse_number(value)?,
+            "termcolors" => {
+                self.term_colors = match value.trim() {
+                    "auto" => None,
+                    value => Some(
+                        ColorDepth::parse(value)
+                            .ok_or(format!("Expected auto, truecolor, 256 or 16: {value}"))?,
+                    ),
+                }
+            }
+            "background" => {
+                self.background = match value.trim() {
+                    "auto" => None,
+                    value => Some(
+                        Background::parse(value)
+                            .ok_or(format!("Expected auto, dark or light: {value}"))?,
+                    ),
+                }
+            }
+            "ambiwidth" => {
+                self.ambiwidth = match value.trim() {
+                    "auto" => None,
+                    value => Some(
+                        AmbiWidth::parse(value)
+                            .ok_or(format!("Expected auto, single or double: {value}"))?,
+                    ),
+                }
+            }
+            "tabstop" | "ts" => {
+                let tabstop = Self::parse_number(value)?;
+                if tabstop == 0 {
+                    return Err("Argument must be positive: tabstop".into());
+                }
+                self.tabs.tabstop = tabstop;
+            }
+            "shiftwidth" | "sw" => self.tabs.shiftwidth = Self::parse_number(value)?,
+            "softtabstop" | "sts" => self.tabs.softtabstop = Self::parse_number(value)?,
+            "listchars" | "lcs" => self.list_chars = ListChars::parse(value.trim())?,
+            "showbreak" | "sbr" => self.showbreak = value.trim().into(),
+            "statusline" | "stl" => self.status_line = StatusLine::parse(value)?,
+            "signcolumn" | "scl" => {
+                self.sign_column = match value.trim() {
+                    "auto" => SignColumn::Auto,
+                    "yes" => SignColumn::Yes,
+                    "no" => SignColumn::No,
+                    value => return Err(format!("Expected auto, yes or no: {value}")),
+                }
+            }
+            name => return Err(format!("Unknown option: {name}")),
+        }
+        Ok(())
+    }
+
+    // The glyphs to draw invisible characters with, if list mode is on
+    pub fn get_list_chars(&self) -> Option<ListChars> {
+        self.list.then_some(self.list_chars)
+    }
+
+    // How long lines are wrapped, if wrapping is on
+    pub fn get_wrap_options(&self) -> Option<WrapOptions> {
+        self.wrap.then(|| WrapOptions {
+            linebreak: self.linebreak,
+            showbreak: self.showbreak.clone(),
+        })
+    }
+
+    pub fn get_line_numbers(&self) -> LineNumbers {
+        match (self.number, self.relative_number) {
+            (false, false) => LineNumbers::Off,
+            (true, false) => LineNumbers::Absolute,
+            (false, true) => LineNumbers::Relative,
+            (true, true) => LineNumbers::Hybrid,
+        }
+    }
+
+    fn set_flag(&mut self, name: &str) -> Result<(), String> {
+        let (name, enabled) = match name.trim().strip_prefix("no") {
+            Some(name) => (name, false),
+            None => (name.trim(), true),
+        };
+        match name {
+            "expandtab" | "et" => self.tabs.expandtab = enabled,
+            "list" => self.list = enabled,
+            "wrap" => self.wrap = enabled,
+            "linebreak" | "lbr" => self.linebreak = enabled,
+            "number" | "nu" => self.number = enabled,
+            "relativenumber" | "rnu" => self.relative_number = enabled,
+            name => return Err(format!("Unknown option: {name}")),
+        }
+        Ok(())
+    }
+
+    fn parse_number<T: std::str::FromStr>(value: &str) -> Result<T, String> {
+        value
+            .trim()
+            .parse()
+            .map_err(|_| format!("Number required: {value}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn list_chars_parse_shows_only_the_given_glyphs() {
+        let list_chars = ListChars::parse("tab:>-|,eol:$").unwrap();
+        let tab = list_chars.tab.map(|tab| (tab.first, tab.fill, tab.last));
+        assert_eq!(tab, Some(('>', '-', Some('|'))));
+        assert_eq!(list_chars.eol, Some('$'));
+        assert_eq!(list_chars.trail, None);
+        assert_eq!(list_chars.zero_width, None);
+        assert!(ListChars::parse("").unwrap().tab.is_none());
+    }
+
+    #[test]
+    fn list_chars_parse_rejects_bad_entries() {
+        assert!(ListChars::parse("tab:>").is_err());
+        assert!(ListChars::parse("trail:~~").is_err());
+        assert!(ListChars::parse("eol:\u{4e00}").is_err());
+        assert!(ListChars::parse("space:.").is_err());
+        assert!(ListChars::parse("trail").is_err());
+    }
+}
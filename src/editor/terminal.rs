@@ -1,18 +1,108 @@
-use super::utility::{Style, TerminalPosition, TerminalSize};
+use super::{
+    color::{ColorDepth, ansi_16_sgr_param, parse_x11_rgb},
+    utility::{CursorShape, Style, TerminalPosition, TerminalSize, UnderlineStyle},
+    width::WidthModel,
+};
 use crossterm::{
-    cursor::{self, MoveTo, RestorePosition, SavePosition},
-    execute, queue,
-    style::{Attribute, Print, SetAttribute, SetBackgroundColor, SetForegroundColor},
+    cursor::{self, MoveTo, RestorePosition, SavePosition, SetCursorStyle},
+    event::{KeyCode, KeyEvent, KeyModifiers},
+    queue,
+    style::{
+        Attribute, Color, Print, SetAttribute, SetBackgroundColor, SetForegroundColor,
+        SetUnderlineColor,
+    },
     terminal::{
-        self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode,
+        self, BeginSynchronizedUpdate, Clear, ClearType, EndSynchronizedUpdate,
+        EnterAlternateScreen, LeaveAlternateScreen, ScrollDown, ScrollUp, disable_raw_mode,
         enable_raw_mode,
     },
 };
-use std::io::{Error, Write};
+use rustix::event::{PollFd, PollFlags, Timespec, poll};
+use std::{
+    cell::{Cell, RefCell},
+    fs::File,
+    io::{Error, Read, Write},
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
+};
+
+// Whether the terminal is currently switched to the editor's state (raw mode, alternate screen)
+// and must be restored before being handed back to the user
+static ACQUIRED: AtomicBool = AtomicBool::new(false);
+
+// How long to wait for the terminal to answer a query before assuming it never will
+const QUERY_TIMEOUT: Duration = Duration::from_millis(500);
+
+// The features of the terminal that can't be assumed and are detected at startup
+#[derive(Default, Clone, Copy)]
+pub struct Capabilities {
+    pub synchronized_output: bool,
+    pub color_depth: ColorDepth,
+    // The colors the terminal draws with when no color is set, if the terminal reports them
+    pub default_foreground: Option<(u8, u8, u8)>,
+    pub default_background: Option<(u8, u8, u8)>,
+    // Whether double, curly, dotted and dashed underlines and underline colors are supported
+    pub styled_underlines: bool,
+    // How the terminal measures the graphemes whose width is disputed
+    pub width_model: WidthModel,
+}
+
+// Bold and dim are turned off by the same SGR code, so each needs to know about the other
+#[derive(Default, Clone, Copy)]
+struct Intensity {
+    bold: bool,
+    dim: bool,
+}
+
+thread_local! {
+    static FRAME: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+    static CAPABILITIES: Cell<Capabilities> = Cell::new(Capabilities::default());
+    static COLOR_DEPTH_OVERRIDE: Cell<Option<ColorDepth>> = const { Cell::new(None) };
+    static INTENSITY: Cell<Intensity> = Cell::new(Intensity::default());
+    // The bytes read while waiting for query replies that weren't part of them, i.e. keys
+    // the user typed meanwhile
+    static PENDING_INPUT: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+}
+
+// Everything sent to the terminal is accumulated here and written out in one go by `flush`,
+// so that the terminal never sees half of a frame
+struct FrameBuffer;
+
+impl Write for FrameBuffer {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        FRAME.with_borrow_mut(|frame| frame.extend_from_slice(buf));
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
 
 pub struct Terminal;
 
 impl Terminal {
+    pub fn acquire() -> Result<(), Error> {
+        ACQUIRED.store(true, Ordering::SeqCst);
+        Self::enter_alternate_screen()?;
+        Self::enable_raw_mode()?;
+        Ok(())
+    }
+
+    // Restore the terminal to the state the user had before `acquire`
+    // Safe to call multiple times, only the first call after `acquire` has any effect
+    pub fn release() -> Result<(), Error> {
+        if !ACQUIRED.swap(false, Ordering::SeqCst) {
+            return Ok(());
+        }
+        Self::end_synchronized_update()?;
+        Self::set_cursor_shape(CursorShape::UserDefault)?;
+        Self::show_cursor()?;
+        Self::disable_raw_mode()?;
+        Self::leave_alternate_screen()?;
+        Ok(())
+    }
+
     pub fn enable_raw_mode() -> Result<(), Error> {
         enable_raw_mode()
     }
@@ -22,15 +112,277 @@ impl Terminal {
     }
 
     pub fn flush() -> Result<(), Error> {
-        std::io::stdout().flush()
+        let frame = FRAME.with_borrow_mut(std::mem::take);
+        let mut stdout = std::io::stdout().lock();
+        stdout.write_all(&frame)?;
+        stdout.flush()
+    }
+
+    pub fn detect_capabilities() -> Result<(), Error> {
+        // DECRQM: the terminal replies with the state of the mode, 0 meaning it is unknown
+        let synchronized_output = Self::query("\x1b[?2026$p")?.is_some_and(|reply| {
+            ["1$y", "2$y", "3$y"]
+                .iter()
+                .any(|state| reply.contains(&format!("\x1b[?2026;{state}")))
+        });
+        // OSC 10/11: the terminal replies with its default colors as `rgb:rrrr/gggg/bbbb`
+        let default_colors = Self::query("\x1b]10;?\x1b\\\x1b]11;?\x1b\\")?.unwrap_or_default();
+        CAPABILITIES.set(Capabilities {
+            synchronized_output,
+            color_depth: ColorDepth::detect(),
+            default_foreground: Self::find_color_reply(&default_colors, 10),
+            default_background: Self::find_color_reply(&default_colors, 11),
+            styled_underlines: Self::detect_styled_underlines(),
+            width_model: Self::probe_width_model()?,
+        });
+        Ok(())
+    }
+
+    // Print each probe at the start of the line followed by a cursor position request (DSR),
+    // the column the cursor is reported at is the width the terminal gave the probe
+    fn probe_width_model() -> Result<WidthModel, Error> {
+        let mut request: String = WidthModel::PROBES
+            .iter()
+            .map(|probe| format!("\r{probe}\x1b[6n"))
+            .collect();
+        request.push_str("\r\x1b[2K");
+        let reply = Self::query(&request)?.unwrap_or_default();
+        // Each report is `CSI row ; col R`
+        let widths: Vec<usize> = reply
+            .split("\x1b[")
+            .filter_map(|report| {
+                let (_, col) = report.strip_suffix('R')?.split_once(';')?;
+                col.parse::<usize>().ok()?.checked_sub(1)
+            })
+            .collect();
+        // Every probe takes at least a column, anything else means the reports can't be trusted
+        if widths.len() != WidthModel::PROBES.len() || widths.contains(&0) {
+            return Ok(WidthModel::default());
+        }
+        Ok(WidthModel::from_probe_widths(&widths))
+    }
+
+    // There is no query for styled underlines, so go by the terminals known to support them
+    fn detect_styled_underlines() -> bool {
+        let term = std::env::var("TERM").unwrap_or_default();
+        let term_program = std::env::var("TERM_PROGRAM")
+            .unwrap_or_default()
+            .to_lowercase();
+        let vte_version: u32 = std::env::var("VTE_VERSION")
+            .ok()
+            .and_then(|version| version.parse().ok())
+            .unwrap_or(0);
+        [
+            "kitty",
+            "wezterm",
+            "foot",
+            "alacritty",
+            "ghostty",
+            "contour",
+        ]
+        .iter()
+        .any(|name| term.contains(name) || term_program.contains(name))
+            || vte_version >= 5102
+    }
+
+    // Find the color in a `OSC code ; rgb:r/g/b ST` reply, ST being either BEL or ESC \
+    fn find_color_reply(reply: &str, code: u8) -> Option<(u8, u8, u8)> {
+        let prefix = format!("\x1b]{code};");
+        let start = reply.find(&prefix)? + prefix.len();
+        let spec = reply[start..].split(['\x07', '\x1b']).next()?;
+        parse_x11_rgb(spec)
+    }
+
+    pub fn get_capabilities() -> Capabilities {
+        CAPABILITIES.get()
+    }
+
+    // Use the given color depth instead of the detected one, `None` to go back to the detected one
+    pub fn override_color_depth(color_depth: Option<ColorDepth>) {
+        COLOR_DEPTH_OVERRIDE.set(color_depth);
+    }
+
+    // 16-color terminals may not understand the `38;5;n` form crossterm uses for named colors
+    fn get_ansi_16_sgr_param(color: Color, foreground: bool) -> Option<u8> {
+        if Self::get_color_depth() != ColorDepth::Ansi16 {
+            return None;
+        }
+        ansi_16_sgr_param(color, foreground)
+    }
+
+    fn get_color_depth() -> ColorDepth {
+        COLOR_DEPTH_OVERRIDE
+            .get()
+            .unwrap_or(Self::get_capabilities().color_depth)
+    }
+
+    // Send a query to the terminal and return everything it replies with
+    // The query is followed by a Primary Device Attributes request, which every terminal answers,
+    // so that terminals ignoring the query are detected without waiting for the timeout
+    // Must be called in raw mode, otherwise the reply is echoed and held back until a newline
+    pub fn query(request: &str) -> Result<Option<String>, Error> {
+        queue!(FrameBuffer, Print(request), Print("\x1b[c"))?;
+        Self::flush()?;
+
+        // Read the terminal directly rather than stdin, whose buffering could swallow
+        // key presses that arrive right after the reply
+        let Ok(mut tty) = File::open("/dev/tty") else {
+            return Ok(None);
+        };
+        let deadline = Instant::now() + QUERY_TIMEOUT;
+        let mut bytes = Vec::new();
+        let mut buf = [0; 256];
+        let device_attributes = loop {
+            if let Some(range) = Self::find_device_attributes(&bytes) {
+                break Some(range);
+            }
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            let Ok(timeout) = Timespec::try_from(timeout) else {
+                break None;
+            };
+            let mut fds = [PollFd::new(&tty, PollFlags::IN)];
+            if timeout == Timespec::default() || poll(&mut fds, Some(&timeout))? == 0 {
+                break None;
+            }
+            match tty.read(&mut buf)? {
+                0 => break None,
+                count => bytes.extend_from_slice(&buf[..count]),
+            }
+        };
+
+        // What follows the device attributes reply can only be typed keys
+        let (before, after) = match device_attributes {
+            Some((start, end)) => (&bytes[..start], &bytes[end..]),
+            None => (&bytes[..], &[][..]),
+        };
+        let (reply, input) = Self::split_replies(before);
+        PENDING_INPUT.with_borrow_mut(|pending| pending.extend(input.iter().chain(after)));
+        Ok(device_attributes.map(|_| reply))
+    }
+
+    // Separate the replies to queries from the keys typed while waiting for them
+    // Replies are OSC strings, cursor position reports and the CSI sequences starting with `?`
+    fn split_replies(bytes: &[u8]) -> (String, Vec<u8>) {
+        let mut reply = Vec::new();
+        let mut input = Vec::new();
+        let mut idx = 0;
+        while idx < bytes.len() {
+            let rest = &bytes[idx..];
+            let (len, is_reply) = if rest.starts_with(b"\x1b]") {
+                let len = (2..rest.len())
+                    .find_map(|end| match rest[end] {
+                        b'\x07' => Some(end + 1),
+                        b'\\' if rest[end - 1] == b'\x1b' => Some(end + 1),
+                        _ => None,
+                    })
+                    .unwrap_or(rest.len());
+                (len, true)
+            } else if rest.starts_with(b"\x1b[") {
+                let len = rest[2..]
+                    .iter()
+                    .position(|byte| (0x40..=0x7e).contains(byte))
+                    .map_or(rest.len(), |end| end + 3);
+                let is_reply = rest.get(2) == Some(&b'?') || rest[len - 1] == b'R';
+                (len, is_reply)
+            } else {
+                (1, false)
+            };
+            let target = if is_reply { &mut reply } else { &mut input };
+            target.extend_from_slice(&rest[..len]);
+            idx += len;
+        }
+        (String::from_utf8_lossy(&reply).into_owned(), input)
+    }
+
+    // The keys typed while the terminal was being queried, which the event reader never saw
+    pub fn take_pending_keys() -> Vec<KeyEvent> {
+        let input = PENDING_INPUT.with_borrow_mut(std::mem::take);
+        Self::parse_keys(&String::from_utf8_lossy(&input))
+    }
+
+    // Turn the bytes of typed keys into key events, for the common keys only
+    fn parse_keys(input: &str) -> Vec<KeyEvent> {
+        let mut keys = Vec::new();
+        let mut chars = input.chars().peekable();
+        while let Some(c) = chars.next() {
+            let (code, modifiers) = match c {
+                '\x1b' if matches!(chars.peek(), Some('[' | 'O')) => {
+                    chars.next();
+                    let mut params = String::new();
+                    let Some(last) = chars.find(|c| {
+                        params.push(*c);
+                        ('\x40'..='\x7e').contains(c)
+                    }) else {
+                        break;
+                    };
+                    params.pop();
+                    let code = match (params.as_str(), last) {
+                        (_, 'A') => KeyCode::Up,
+                        (_, 'B') => KeyCode::Down,
+                        (_, 'C') => KeyCode::Right,
+                        (_, 'D') => KeyCode::Left,
+                        (_, 'H') | ("1" | "7", '~') => KeyCode::Home,
+                        (_, 'F') | ("4" | "8", '~') => KeyCode::End,
+                        ("2", '~') => KeyCode::Insert,
+                        ("3", '~') => KeyCode::Delete,
+                        ("5", '~') => KeyCode::PageUp,
+                        ("6", '~') => KeyCode::PageDown,
+                        _ => continue,
+                    };
+                    (code, KeyModifiers::NONE)
+                }
+                '\x1b' => (KeyCode::Esc, KeyModifiers::NONE),
+                '\r' | '\n' => (KeyCode::Enter, KeyModifiers::NONE),
+                '\t' => (KeyCode::Tab, KeyModifiers::NONE),
+                '\x7f' | '\x08' => (KeyCode::Backspace, KeyModifiers::NONE),
+                '\x01'..='\x1a' => (
+                    KeyCode::Char((c as u8 - 1 + b'a') as char),
+                    KeyModifiers::CONTROL,
+                ),
+                c if c.is_uppercase() => (KeyCode::Char(c), KeyModifiers::SHIFT),
+                c if !c.is_control() => (KeyCode::Char(c), KeyModifiers::NONE),
+                _ => continue,
+            };
+            keys.push(KeyEvent::new(code, modifiers));
+        }
+        keys
+    }
+
+    // Find where a complete Primary Device Attributes reply (`CSI ? Ps ; ... c`) starts and
+    // ends, keys typed right after it possibly having arrived in the same read
+    fn find_device_attributes(bytes: &[u8]) -> Option<(usize, usize)> {
+        (0..bytes.len()).find_map(|start| {
+            let params = bytes[start..].strip_prefix(b"\x1b[?")?;
+            let len = params
+                .iter()
+                .position(|byte| !byte.is_ascii_digit() && *byte != b';')?;
+            (params[len] == b'c').then_some((start, start + 3 + len + 1))
+        })
+    }
+
+    // Ask the terminal to hold off repainting until the frame is complete, if it supports that
+    pub fn begin_synchronized_update() -> Result<(), Error> {
+        if !Self::get_capabilities().synchronized_output {
+            return Ok(());
+        }
+        queue!(FrameBuffer, BeginSynchronizedUpdate)
+    }
+
+    pub fn end_synchronized_update() -> Result<(), Error> {
+        if !Self::get_capabilities().synchronized_output {
+            return Ok(());
+        }
+        queue!(FrameBuffer, EndSynchronizedUpdate)
     }
 
     pub fn enter_alternate_screen() -> Result<(), Error> {
-        execute!(std::io::stdout(), EnterAlternateScreen)
+        queue!(FrameBuffer, EnterAlternateScreen)?;
+        Self::flush()
     }
 
     pub fn leave_alternate_screen() -> Result<(), Error> {
-        execute!(std::io::stdout(), LeaveAlternateScreen)
+        queue!(FrameBuffer, LeaveAlternateScreen)?;
+        Self::flush()
     }
 
     pub fn get_size() -> Result<TerminalSize, Error> {
@@ -39,73 +391,207 @@ impl Terminal {
     }
 
     pub fn clear_screen() -> Result<(), Error> {
-        queue!(std::io::stdout(), Clear(ClearType::All))
+        queue!(FrameBuffer, Clear(ClearType::All))
     }
 
     pub fn move_to(pos: TerminalPosition) -> Result<(), Error> {
-        queue!(std::io::stdout(), MoveTo(pos.col, pos.row))
+        queue!(FrameBuffer, MoveTo(pos.col, pos.row))
     }
 
     pub fn print(line: &str) -> Result<(), Error> {
-        queue!(std::io::stdout(), Print(line))
+        queue!(FrameBuffer, Print(line))
     }
 
+    #[allow(dead_code)]
     pub fn save_cursor_position() -> Result<(), Error> {
-        queue!(std::io::stdout(), SavePosition)
+        queue!(FrameBuffer, SavePosition)
     }
 
+    #[allow(dead_code)]
     pub fn restore_cursor_position() -> Result<(), Error> {
-        queue!(std::io::stdout(), RestorePosition)
+        queue!(FrameBuffer, RestorePosition)
+    }
+
+    // Restrict scrolling to the rows between `top` and `bottom` (inclusive) with DECSTBM
+    pub fn set_scroll_region(top: u16, bottom: u16) -> Result<(), Error> {
+        queue!(
+            FrameBuffer,
+            Print(format!("\x1b[{};{}r", top + 1, bottom + 1))
+        )
+    }
+
+    pub fn reset_scroll_region() -> Result<(), Error> {
+        queue!(FrameBuffer, Print("\x1b[r"))
+    }
+
+    pub fn scroll_up(rows: u16) -> Result<(), Error> {
+        queue!(FrameBuffer, ScrollUp(rows))
+    }
+
+    pub fn scroll_down(rows: u16) -> Result<(), Error> {
+        queue!(FrameBuffer, ScrollDown(rows))
     }
 
     pub fn clear_line() -> Result<(), Error> {
-        queue!(std::io::stdout(), Clear(ClearType::CurrentLine))
+        queue!(FrameBuffer, Clear(ClearType::CurrentLine))
+    }
+
+    // Turn off every attribute and go back to the default colors
+    pub fn reset_style() -> Result<(), Error> {
+        INTENSITY.set(Intensity::default());
+        queue!(FrameBuffer, SetAttribute(Attribute::Reset))
     }
 
     pub fn set_style(style: Style) -> Result<(), Error> {
         match style {
-            Style::Bold(enabled) => {
+            Style::Bold(bold) => Self::set_intensity(Intensity {
+                bold,
+                ..INTENSITY.get()
+            }),
+            Style::Dim(dim) => Self::set_intensity(Intensity {
+                dim,
+                ..INTENSITY.get()
+            }),
+            Style::Italic(enabled) => {
                 if enabled {
-                    queue!(std::io::stdout(), SetAttribute(Attribute::Bold))
+                    queue!(FrameBuffer, SetAttribute(Attribute::Italic))
                 } else {
-                    queue!(std::io::stdout(), SetAttribute(Attribute::NoBold))
+                    queue!(FrameBuffer, SetAttribute(Attribute::NoItalic))
                 }
             }
-            Style::Italic(enabled) => {
+            Style::Underline(underline) => {
+                let styled_underlines = Self::get_capabilities().styled_underlines;
+                let attribute = match underline {
+                    UnderlineStyle::None => Attribute::NoUnderline,
+                    UnderlineStyle::Single => Attribute::Underlined,
+                    _ if !styled_underlines => Attribute::Underlined,
+                    UnderlineStyle::Double => Attribute::DoubleUnderlined,
+                    UnderlineStyle::Curly => Attribute::Undercurled,
+                    UnderlineStyle::Dotted => Attribute::Underdotted,
+                    UnderlineStyle::Dashed => Attribute::Underdashed,
+                };
+                queue!(FrameBuffer, SetAttribute(attribute))
+            }
+            Style::UnderlineColor(color) => {
+                if !Self::get_capabilities().styled_underlines {
+                    return Ok(());
+                }
+                match Self::get_color_depth().quantize(color) {
+                    // SGR 59 restores the default underline color, i.e. the text color
+                    Color::Reset => queue!(FrameBuffer, Print("\x1b[59m")),
+                    color => queue!(FrameBuffer, SetUnderlineColor(color)),
+                }
+            }
+            Style::Strikethrough(enabled) => {
                 if enabled {
-                    queue!(std::io::stdout(), SetAttribute(Attribute::Italic))
+                    queue!(FrameBuffer, SetAttribute(Attribute::CrossedOut))
                 } else {
-                    queue!(std::io::stdout(), SetAttribute(Attribute::NoItalic))
+                    queue!(FrameBuffer, SetAttribute(Attribute::NotCrossedOut))
                 }
             }
-            Style::Underlined(enabled) => {
+            Style::Blink(enabled) => {
                 if enabled {
-                    queue!(std::io::stdout(), SetAttribute(Attribute::Underlined))
+                    queue!(FrameBuffer, SetAttribute(Attribute::SlowBlink))
                 } else {
-                    queue!(std::io::stdout(), SetAttribute(Attribute::NoUnderline))
+                    queue!(FrameBuffer, SetAttribute(Attribute::NoBlink))
                 }
             }
             Style::Inverted(enabled) => {
                 if enabled {
-                    queue!(std::io::stdout(), SetAttribute(Attribute::Reverse))
+                    queue!(FrameBuffer, SetAttribute(Attribute::Reverse))
                 } else {
-                    queue!(std::io::stdout(), SetAttribute(Attribute::NoReverse))
+                    queue!(FrameBuffer, SetAttribute(Attribute::NoReverse))
                 }
             }
             Style::Foreground(color) => {
-                queue!(std::io::stdout(), SetForegroundColor(color))
+                let color = Self::get_color_depth().quantize(color);
+                match Self::get_ansi_16_sgr_param(color, true) {
+                    Some(param) => queue!(FrameBuffer, Print(format!("\x1b[{param}m"))),
+                    None => queue!(FrameBuffer, SetForegroundColor(color)),
+                }
             }
             Style::Background(color) => {
-                queue!(std::io::stdout(), SetBackgroundColor(color))
+                let color = Self::get_color_depth().quantize(color);
+                match Self::get_ansi_16_sgr_param(color, false) {
+                    Some(param) => queue!(FrameBuffer, Print(format!("\x1b[{param}m"))),
+                    None => queue!(FrameBuffer, SetBackgroundColor(color)),
+                }
             }
         }
     }
 
+    // SGR 22 turns off both bold and dim (SGR 21 is double underline on many terminals),
+    // so whichever should stay on is turned back on afterwards
+    fn set_intensity(intensity: Intensity) -> Result<(), Error> {
+        INTENSITY.set(intensity);
+        queue!(FrameBuffer, SetAttribute(Attribute::NormalIntensity))?;
+        if intensity.bold {
+            queue!(FrameBuffer, SetAttribute(Attribute::Bold))?;
+        }
+        if intensity.dim {
+            queue!(FrameBuffer, SetAttribute(Attribute::Dim))?;
+        }
+        Ok(())
+    }
+
     pub fn hide_cursor() -> Result<(), Error> {
-        execute!(std::io::stdout(), cursor::Hide)
+        queue!(FrameBuffer, cursor::Hide)
     }
 
     pub fn show_cursor() -> Result<(), Error> {
-        execute!(std::io::stdout(), cursor::Show)
+        queue!(FrameBuffer, cursor::Show)
+    }
+
+    pub fn set_cursor_shape(shape: CursorShape) -> Result<(), Error> {
+        let style = match shape {
+            CursorShape::UserDefault => SetCursorStyle::DefaultUserShape,
+            CursorShape::Block => SetCursorStyle::SteadyBlock,
+            CursorShape::BlinkingBlock => SetCursorStyle::BlinkingBlock,
+            CursorShape::Bar => SetCursorStyle::SteadyBar,
+            CursorShape::BlinkingBar => SetCursorStyle::BlinkingBar,
+            CursorShape::Underline => SetCursorStyle::SteadyUnderScore,
+            CursorShape::BlinkingUnderline => SetCursorStyle::BlinkingUnderScore,
+        };
+        queue!(FrameBuffer, style)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_replies_keeps_typed_keys() {
+        let (reply, input) =
+            Terminal::split_replies(b"ab\x1b[?2026;2$y\x1b[A\x1b]11;rgb:0/0/0\x1b\\\x1b[3;5Rc");
+        assert_eq!(reply, "\x1b[?2026;2$y\x1b]11;rgb:0/0/0\x1b\\\x1b[3;5R");
+        assert_eq!(input, b"ab\x1b[Ac");
+    }
+
+    #[test]
+    fn find_device_attributes_before_typed_keys() {
+        let bytes = b"\x1b]11;rgb:0/0/0\x07\x1b[?62;22cjk";
+        assert_eq!(Terminal::find_device_attributes(bytes), Some((15, 24)));
+        assert_eq!(Terminal::find_device_attributes(b"\x1b[?62;2"), None);
+        assert_eq!(Terminal::find_device_attributes(b"\x1b[?2026;2$y"), None);
+    }
+
+    #[test]
+    fn parse_keys_reads_common_keys() {
+        let keys = Terminal::parse_keys("iA\x1b\x17\r\x1b[B\x1b[3~");
+        let codes: Vec<(KeyCode, KeyModifiers)> =
+            keys.iter().map(|key| (key.code, key.modifiers)).collect();
+        assert_eq!(
+            codes,
+            [
+                (KeyCode::Char('i'), KeyModifiers::NONE),
+                (KeyCode::Char('A'), KeyModifiers::SHIFT),
+                (KeyCode::Esc, KeyModifiers::NONE),
+                (KeyCode::Char('w'), KeyModifiers::CONTROL),
+                (KeyCode::Enter, KeyModifiers::NONE),
+                (KeyCode::Down, KeyModifiers::NONE),
+                (KeyCode::Delete, KeyModifiers::NONE),
+            ]
+        );
     }
 }
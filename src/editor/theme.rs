@@ -0,0 +1,332 @@
+use super::utility::{Style, UnderlineStyle};
+use crossterm::style::Color;
+use std::{collections::HashMap, fs, path::PathBuf};
+
+// The theme used when no colorscheme has been chosen, in the same format as theme files
+// Normal is left out so that the terminal's own default colors are used
+const DEFAULT_THEME: &str = "
+StatusLine inverse
+StatusLineNC inverse dim
+StatusLineMode bold
+TabLine inverse dim
+TabLineSel bold
+TabLineFill inverse dim
+Visual inverse
+ErrorMsg fg=white bg=dark_red
+Whitespace dim
+
+[dark]
+Search fg=black bg=yellow
+LineNr fg=dark_grey
+CursorLineNr fg=yellow bold
+NonText fg=blue
+MarkSign fg=cyan bold
+WarningMsg fg=yellow bold
+MoreMsg fg=green bold
+WinSeparator fg=dark_grey
+NormalFloat bg=dark_grey
+FloatBorder fg=grey bg=dark_grey
+
+[light]
+Search fg=black bg=cyan
+LineNr fg=grey
+CursorLineNr fg=dark_yellow bold
+NonText fg=dark_blue
+MarkSign fg=dark_cyan bold
+WarningMsg fg=dark_yellow bold
+MoreMsg fg=dark_green bold
+WinSeparator fg=grey
+NormalFloat bg=grey
+FloatBorder fg=dark_grey bg=grey
+";
+
+// Whether the terminal background is dark or light, so that themes can pick readable colors
+#[derive(Default, PartialEq, Eq, Clone, Copy)]
+pub enum Background {
+    #[default]
+    Dark,
+    Light,
+}
+
+impl Background {
+    pub fn parse(name: &str) -> Option<Background> {
+        match name {
+            "dark" => Some(Background::Dark),
+            "light" => Some(Background::Light),
+            _ => None,
+        }
+    }
+
+    // Classify a background color by its relative luminance
+    pub fn from_rgb((r, g, b): (u8, u8, u8)) -> Background {
+        let luminance = 0.2126 * r as f32 + 0.7152 * g as f32 + 0.0722 * b as f32;
+        if luminance < 128.0 {
+            Background::Dark
+        } else {
+            Background::Light
+        }
+    }
+}
+
+// The named roles that parts of the UI are drawn with, their actual styles come from the theme
+#[derive(PartialEq, Eq, Hash, Clone, Copy)]
+pub enum HighlightGroup {
+    Normal,
+    StatusLine,
+    StatusLineNC,
+    StatusLineMode,
+    Visual,
+    Search,
+    LineNr,
+    CursorLineNr,
+    NonText,
+    Whitespace,
+    SignColumn,
+    MarkSign,
+    ErrorMsg,
+    WarningMsg,
+    MoreMsg,
+    WinSeparator,
+    TabLine,
+    TabLineSel,
+    TabLineFill,
+    NormalFloat,
+    FloatBorder,
+}
+
+impl HighlightGroup {
+    pub fn from_name(name: &str) -> Option<HighlightGroup> {
+        match name {
+            "Normal" => Some(HighlightGroup::Normal),
+            "StatusLine" => Some(HighlightGroup::StatusLine),
+            "StatusLineNC" => Some(HighlightGroup::StatusLineNC),
+            "StatusLineMode" => Some(HighlightGroup::StatusLineMode),
+            "Visual" => Some(HighlightGroup::Visual),
+            "Search" => Some(HighlightGroup::Search),
+            "LineNr" => Some(HighlightGroup::LineNr),
+            "CursorLineNr" => Some(HighlightGroup::CursorLineNr),
+            "NonText" => Some(HighlightGroup::NonText),
+            "Whitespace" => Some(HighlightGroup::Whitespace),
+            "SignColumn" => Some(HighlightGroup::SignColumn),
+            "MarkSign" => Some(HighlightGroup::MarkSign),
+            "ErrorMsg" => Some(HighlightGroup::ErrorMsg),
+            "WarningMsg" => Some(HighlightGroup::WarningMsg),
+            "MoreMsg" => Some(HighlightGroup::MoreMsg),
+            "WinSeparator" => Some(HighlightGroup::WinSeparator),
+            "TabLine" => Some(HighlightGroup::TabLine),
+            "TabLineSel" => Some(HighlightGroup::TabLineSel),
+            "TabLineFill" => Some(HighlightGroup::TabLineFill),
+            "NormalFloat" => Some(HighlightGroup::NormalFloat),
+            "FloatBorder" => Some(HighlightGroup::FloatBorder),
+            _ => None,
+        }
+    }
+
+    // Where overlapping highlights meet, each attribute (colors, bold, ...) is taken from the
+    // highest priority group that sets it, so e.g. a selection over a search match replaces
+    // the match's background but keeps its foreground if the selection sets none
+    pub fn get_priority(&self) -> u8 {
+        match self {
+            HighlightGroup::Normal => 0,
+            // The Normal of popups
+            HighlightGroup::NormalFloat => 5,
+            HighlightGroup::NonText
+            | HighlightGroup::Whitespace
+            | HighlightGroup::LineNr
+            | HighlightGroup::CursorLineNr
+            | HighlightGroup::SignColumn
+            | HighlightGroup::WinSeparator
+            | HighlightGroup::FloatBorder => 10,
+            // Signs are drawn over the sign column
+            HighlightGroup::MarkSign => 15,
+            HighlightGroup::Search => 20,
+            HighlightGroup::Visual => 30,
+            HighlightGroup::StatusLine
+            | HighlightGroup::StatusLineNC
+            | HighlightGroup::ErrorMsg
+            | HighlightGroup::WarningMsg
+            | HighlightGroup::MoreMsg
+            | HighlightGroup::TabLine
+            | HighlightGroup::TabLineSel
+            | HighlightGroup::TabLineFill => 40,
+            // Parts of the status line are drawn over it
+            HighlightGroup::StatusLineMode => 45,
+        }
+    }
+}
+
+// A theme maps highlight groups to styles
+// Theme files contain one group per line: the group name followed by its attributes, e.g.
+// `StatusLine fg=#ffffff bg=dark_blue bold`
+// `ul=` sets the underline color, `nounderline` turns off an underline from a lower group
+// Colors are color names, `default`, ANSI color numbers or `#rrggbb`
+// Lines after a `[dark]` or `[light]` header only apply on that kind of background
+// Groups missing from the theme are drawn with the Normal style
+pub struct Theme {
+    name: String,
+    background: Background,
+    groups: HashMap<HighlightGroup, Vec<Style>>,
+}
+
+impl Default for Theme {
+    fn default() -> Theme {
+        Theme::parse("default", DEFAULT_THEME, Background::default()).unwrap_or(Theme {
+            name: "default".into(),
+            background: Background::default(),
+            groups: HashMap::new(),
+        })
+    }
+}
+
+impl Theme {
+    pub fn load(name: &str, background: Background) -> Result<Theme, String> {
+        if name == "default" {
+            return Theme::parse(name, DEFAULT_THEME, background);
+        }
+        let path = Self::get_theme_dirs()
+            .into_iter()
+            .map(|dir| dir.join(format!("{name}.theme")))
+            .find(|path| path.exists())
+            .ok_or(format!("Cannot find colorscheme '{name}'"))?;
+        let content = fs::read_to_string(&path)
+            .map_err(|error| format!("Cannot read {}: {error}", path.display()))?;
+        Theme::parse(name, &content, background)
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_background(&self) -> Background {
+        self.background
+    }
+
+    pub fn get_styles(&self, group: HighlightGroup) -> &[Style] {
+        self.groups.get(&group).map_or(&[], |styles| styles)
+    }
+
+    fn get_theme_dirs() -> Vec<PathBuf> {
+        let mut dirs = vec![];
+        if let Some(config_home) = std::env::var_os("XDG_CONFIG_HOME") {
+            dirs.push(PathBuf::from(config_home).join("t-rex/colors"));
+        }
+        if let Some(home) = std::env::var_os("HOME") {
+            dirs.push(PathBuf::from(home).join(".config/t-rex/colors"));
+        }
+        dirs
+    }
+
+    fn parse(name: &str, content: &str, background: Background) -> Result<Theme, String> {
+        let mut groups = HashMap::new();
+        let mut section_background = None;
+        for (line_idx, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |message: String| format!("{name}.theme line {}: {message}", line_idx + 1);
+            if let Some(section) = line
+                .strip_prefix('[')
+                .and_then(|line| line.strip_suffix(']'))
+            {
+                section_background = Some(
+                    Background::parse(section)
+                        .ok_or_else(|| error(format!("Unknown section {section}")))?,
+                );
+                continue;
+            }
+            if section_background.is_some_and(|section| section != background) {
+                continue;
+            }
+
+            let mut words = line.split_whitespace();
+            let group_name = words.next().unwrap_or_default();
+            let group = HighlightGroup::from_name(group_name)
+                .ok_or_else(|| error(format!("Unknown highlight group {group_name}")))?;
+            let styles = words
+                .map(Self::parse_style)
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(error)?;
+            groups.insert(group, styles);
+        }
+        Ok(Theme {
+            name: name.into(),
+            background,
+            groups,
+        })
+    }
+
+    fn parse_style(word: &str) -> Result<Style, String> {
+        if let Some(color) = word.strip_prefix("fg=") {
+            return Ok(Style::Foreground(Self::parse_color(color)?));
+        }
+        if let Some(color) = word.strip_prefix("bg=") {
+            return Ok(Style::Background(Self::parse_color(color)?));
+        }
+        if let Some(color) = word.strip_prefix("ul=") {
+            return Ok(Style::UnderlineColor(Self::parse_color(color)?));
+        }
+        match word {
+            "bold" => Ok(Style::Bold(true)),
+            "dim" => Ok(Style::Dim(true)),
+            "italic" => Ok(Style::Italic(true)),
+            "underline" => Ok(Style::Underline(UnderlineStyle::Single)),
+            "nounderline" => Ok(Style::Underline(UnderlineStyle::None)),
+            "double-underline" => Ok(Style::Underline(UnderlineStyle::Double)),
+            "curly-underline" => Ok(Style::Underline(UnderlineStyle::Curly)),
+            "dotted-underline" => Ok(Style::Underline(UnderlineStyle::Dotted)),
+            "dashed-underline" => Ok(Style::Underline(UnderlineStyle::Dashed)),
+            "strikethrough" => Ok(Style::Strikethrough(true)),
+            "blink" => Ok(Style::Blink(true)),
+            "inverse" => Ok(Style::Inverted(true)),
+            _ => Err(format!("Unknown attribute {word}")),
+        }
+    }
+
+    fn parse_color(color: &str) -> Result<Color, String> {
+        if color == "default" || color == "none" {
+            return Ok(Color::Reset);
+        }
+        if let Some(hex) = color.strip_prefix('#')
+            && hex.len() == 6
+            && hex.is_ascii()
+        {
+            let channel = |idx: usize| u8::from_str_radix(&hex[idx..idx + 2], 16);
+            if let (Ok(r), Ok(g), Ok(b)) = (channel(0), channel(2), channel(4)) {
+                return Ok(Color::Rgb { r, g, b });
+            }
+        }
+        if let Ok(value) = color.parse::<u8>() {
+            return Ok(Color::AnsiValue(value));
+        }
+        Color::try_from(color).map_err(|_| format!("Unknown color {color}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_color_reads_every_form() {
+        assert_eq!(Theme::parse_color("none"), Ok(Color::Reset));
+        assert_eq!(
+            Theme::parse_color("#1a2B3c"),
+            Ok(Color::Rgb {
+                r: 0x1a,
+                g: 0x2b,
+                b: 0x3c
+            })
+        );
+        assert_eq!(Theme::parse_color("208"), Ok(Color::AnsiValue(208)));
+        assert_eq!(Theme::parse_color("dark_blue"), Ok(Color::DarkBlue));
+    }
+
+    #[test]
+    fn parse_color_rejects_bad_hex() {
+        assert!(Theme::parse_color("#12345").is_err());
+        assert!(Theme::parse_color("#12345g").is_err());
+        // Six bytes, but a multi-byte character straddles the channel boundaries
+        assert!(Theme::parse_color("#\u{e9}1234").is_err());
+    }
+}
@@ -81,12 +81,56 @@ impl TerminalArea {
     }
 }
 
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub enum UnderlineStyle {
+    // No underline, so a highlight can turn off the underline of one below it
+    None,
+    Single,
+    Double,
+    Curly,
+    Dotted,
+    Dashed,
+}
+
 #[derive(PartialEq, Eq, Clone)]
 pub enum Style {
     Bold(bool),
+    Dim(bool),
     Italic(bool),
-    Underlined(bool),
+    Underline(UnderlineStyle),
+    UnderlineColor(Color),
+    Strikethrough(bool),
+    Blink(bool),
     Inverted(bool),
     Foreground(Color),
     Background(Color),
 }
+
+// The shape of the terminal cursor, `UserDefault` being whatever the user has configured
+// in their terminal emulator
+#[derive(Default, PartialEq, Eq, Clone, Copy)]
+pub enum CursorShape {
+    #[default]
+    UserDefault,
+    Block,
+    BlinkingBlock,
+    Bar,
+    BlinkingBar,
+    Underline,
+    BlinkingUnderline,
+}
+
+impl CursorShape {
+    pub fn parse(name: &str) -> Option<CursorShape> {
+        match name {
+            "default" => Some(CursorShape::UserDefault),
+            "block" => Some(CursorShape::Block),
+            "blinking-block" => Some(CursorShape::BlinkingBlock),
+            "bar" => Some(CursorShape::Bar),
+            "blinking-bar" => Some(CursorShape::BlinkingBar),
+            "underline" => Some(CursorShape::Underline),
+            "blinking-underline" => Some(CursorShape::BlinkingUnderline),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,132 @@
+use std::cell::Cell;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+const ZERO_WIDTH_JOINER: char = '\u{200D}';
+const EMOJI_PRESENTATION_SELECTOR: char = '\u{FE0F}';
+
+// How wide the terminal draws the graphemes that unicode-width and terminals often disagree on
+// The defaults are what unicode-width assumes
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub struct WidthModel {
+    // East Asian Ambiguous characters (e.g. `①`, `…` in some fonts) take 2 columns
+    pub ambiguous_wide: bool,
+    // Emoji joined by a zero-width joiner are drawn as one emoji rather than one after another
+    pub zwj_joined: bool,
+    // A text-default emoji followed by U+FE0F is drawn double-width
+    pub emoji_presentation_wide: bool,
+    // The columns a pair of regional indicators takes, 2 when they are drawn as one flag
+    pub flag_width: usize,
+}
+
+impl Default for WidthModel {
+    fn default() -> WidthModel {
+        WidthModel {
+            ambiguous_wide: false,
+            zwj_joined: true,
+            emoji_presentation_wide: true,
+            flag_width: 2,
+        }
+    }
+}
+
+// Whether East Asian Ambiguous characters are single or double width, as the `ambiwidth` option
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub enum AmbiWidth {
+    Single,
+    Double,
+}
+
+impl AmbiWidth {
+    pub fn parse(name: &str) -> Option<AmbiWidth> {
+        match name {
+            "single" => Some(AmbiWidth::Single),
+            "double" => Some(AmbiWidth::Double),
+            _ => None,
+        }
+    }
+}
+
+impl WidthModel {
+    // The graphemes printed to the terminal to find out how it draws each case, in order
+    pub const PROBES: [&str; 4] = [
+        "\u{2460}",
+        "\u{1F468}\u{200D}\u{1F469}",
+        "\u{263A}\u{FE0F}",
+        "\u{1F1FA}\u{1F1F8}",
+    ];
+
+    // Build the model from the widths the terminal advanced the cursor by for each of `PROBES`
+    pub fn from_probe_widths(widths: &[usize]) -> WidthModel {
+        let default = WidthModel::default();
+        let probed = |idx: usize, wide_width: usize, default: bool| {
+            widths
+                .get(idx)
+                .map_or(default, |width| *width == wide_width)
+        };
+        WidthModel {
+            ambiguous_wide: probed(0, 2, default.ambiguous_wide),
+            zwj_joined: probed(1, 2, default.zwj_joined),
+            emoji_presentation_wide: probed(2, 2, default.emoji_presentation_wide),
+            flag_width: widths.get(3).copied().unwrap_or(default.flag_width),
+        }
+    }
+}
+
+thread_local! {
+    static WIDTH_MODEL: Cell<WidthModel> = Cell::new(WidthModel::default());
+}
+
+pub fn set_width_model(model: WidthModel) {
+    WIDTH_MODEL.set(model);
+}
+
+// The number of columns a string takes on the terminal, according to the current width model
+// Every width computation for display must go through this, so that the cursor and the text agree
+pub trait DisplayWidth {
+    fn display_width(&self) -> usize;
+}
+
+impl DisplayWidth for str {
+    fn display_width(&self) -> usize {
+        self.graphemes(true).map(grapheme_width).sum()
+    }
+}
+
+fn grapheme_width(grapheme: &str) -> usize {
+    let model = WIDTH_MODEL.get();
+    if !model.zwj_joined && grapheme.contains(ZERO_WIDTH_JOINER) {
+        return grapheme
+            .split(ZERO_WIDTH_JOINER)
+            .map(|part| base_width(part, model))
+            .sum();
+    }
+    if is_flag(grapheme) {
+        return model.flag_width;
+    }
+    if !model.emoji_presentation_wide && grapheme.contains(EMOJI_PRESENTATION_SELECTOR) {
+        return grapheme
+            .chars()
+            .filter(|c| *c != EMOJI_PRESENTATION_SELECTOR)
+            .map(|c| c.width().unwrap_or(0))
+            .sum();
+    }
+    base_width(grapheme, model)
+}
+
+fn base_width(text: &str, model: WidthModel) -> usize {
+    if model.ambiguous_wide {
+        text.width_cjk()
+    } else {
+        text.width()
+    }
+}
+
+fn is_flag(grapheme: &str) -> bool {
+    let mut chars = grapheme.chars();
+    let is_regional_indicator =
+        |c: Option<char>| c.is_some_and(|c| ('\u{1F1E6}'..='\u{1F1FF}').contains(&c));
+    is_regional_indicator(chars.next())
+        && is_regional_indicator(chars.next())
+        && chars.next().is_none()
+}
@@ -2,8 +2,10 @@ use std::path::PathBuf;
 use t_rex::editor::Editor;
 
 fn main() {
-    let editor = Editor::default();
-    let args: Vec<String> = std::env::args().collect();
-    let path = args.get(1).map(|path| PathBuf::from(path));
-    editor.run(path).unwrap();
+    let editor = Editor;
+    let paths = std::env::args().skip(1).map(PathBuf::from).collect();
+    if let Err(error) = editor.run(paths) {
+        eprintln!("t-rex: {error}");
+        std::process::exit(1);
+    }
 }